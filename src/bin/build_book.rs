@@ -0,0 +1,443 @@
+//! Builds a Polyglot opening book from PGN game collections, for `BOT_BOOK_FILE`.
+//!
+//! usage: build_book [options] <output> <games.pgn>...
+//!
+//! options:
+//!   --max-ply <n>        only count the first n half-moves of every game (default 20)
+//!   --min-rating <n>     skip games where a player is rated below n or not rated at all
+//!   --results <list>     only use games with these results, e.g. `1-0,0-1` (default all)
+//!   --min-games <n>      leave out moves played in fewer than n games (default 1)
+//!
+//! A move's weight is twice its wins plus its draws, from the point of view of the side playing
+//! it, so the bot prefers moves that scored well. Moves that never scored anything are left out.
+
+use std::{
+    collections::HashMap,
+    env,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    time::Instant,
+};
+
+use anyhow::{Context, Result, bail};
+use rusty_lichess_bot::{engine::OpeningBook, util};
+use shakmaty::{CastlingMode, Chess, Color, Move, Position, fen::Fen};
+
+const DEFAULT_MAX_PLY: usize = 20;
+/// number of the most played first moves shown in the summary
+const SUMMARY_MOVES: usize = 5;
+
+struct Options {
+    max_ply: usize,
+    min_rating: Option<u32>,
+    /// accepted results, all if empty
+    results: Vec<String>,
+    min_games: u32,
+    output: PathBuf,
+    inputs: Vec<PathBuf>,
+}
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut options = Options {
+            max_ply: DEFAULT_MAX_PLY,
+            min_rating: None,
+            results: Vec::new(),
+            min_games: 1,
+            output: PathBuf::new(),
+            inputs: Vec::new(),
+        };
+        let mut paths = Vec::new();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .with_context(|| format!("missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--max-ply" => options.max_ply = value()?.parse().context("invalid --max-ply")?,
+                "--min-rating" => {
+                    options.min_rating = Some(value()?.parse().context("invalid --min-rating")?)
+                }
+                "--results" => {
+                    options.results = value()?.split(',').map(str::to_string).collect();
+                }
+                "--min-games" => {
+                    options.min_games = value()?.parse().context("invalid --min-games")?
+                }
+                flag if flag.starts_with("--") => bail!("unknown option {flag}"),
+                _ => paths.push(PathBuf::from(arg)),
+            }
+        }
+        if paths.len() < 2 {
+            bail!("usage: build_book [options] <output> <games.pgn>...");
+        }
+        options.output = paths.remove(0);
+        options.inputs = paths;
+        Ok(options)
+    }
+}
+
+/// one game as read from a PGN file
+#[derive(Default)]
+struct PgnGame {
+    headers: HashMap<String, String>,
+    /// moves in SAN, without move numbers, annotations and variations
+    moves: Vec<String>,
+    /// result from the end of the movetext, for games without a result header
+    termination: Option<String>,
+}
+impl PgnGame {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    fn result(&self) -> Option<&str> {
+        self.header("Result").or(self.termination.as_deref())
+    }
+
+    /// for the report of skipped games
+    fn describe(&self) -> String {
+        format!(
+            "{} - {} ({})",
+            self.header("White").unwrap_or("?"),
+            self.header("Black").unwrap_or("?"),
+            self.header("Site").or(self.header("Event")).unwrap_or("?")
+        )
+    }
+}
+
+/// splits PGN text into games, line by line so huge collections don't have to fit in memory
+#[derive(Default)]
+struct PgnReader {
+    game: PgnGame,
+    in_comment: bool,
+    variation_depth: usize,
+}
+impl PgnReader {
+    /// feeds the next line, returns the game it completed if any
+    fn read_line(&mut self, line: &str) -> Option<PgnGame> {
+        let trimmed = line.trim();
+        let outside_movetext = !self.in_comment && self.variation_depth == 0;
+        if outside_movetext && trimmed.starts_with('%') {
+            return None; // escaped line
+        }
+        if outside_movetext && trimmed.starts_with('[') {
+            // headers after moves start the next game
+            let finished = self.finish_if_started();
+            if let Some((name, value)) = parse_header(trimmed) {
+                self.game.headers.insert(name, value);
+            }
+            return finished;
+        }
+
+        let mut rest = line;
+        while !rest.is_empty() {
+            if self.in_comment {
+                match rest.find('}') {
+                    Some(end) => {
+                        self.in_comment = false;
+                        rest = &rest[end + 1..];
+                    }
+                    None => break,
+                }
+                continue;
+            }
+            let c = rest.chars().next().unwrap();
+            match c {
+                '{' => self.in_comment = true,
+                ';' => break, // comment until the end of the line
+                '(' => self.variation_depth += 1,
+                ')' => self.variation_depth = self.variation_depth.saturating_sub(1),
+                c if c.is_whitespace() => {}
+                _ => {
+                    let end = rest
+                        .find(|c: char| c.is_whitespace() || "{}();".contains(c))
+                        .unwrap_or(rest.len());
+                    let token = &rest[..end];
+                    rest = &rest[end..];
+                    if self.variation_depth == 0
+                        && let Some(game) = self.read_token(token)
+                    {
+                        return Some(game);
+                    }
+                    continue;
+                }
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+        None
+    }
+
+    fn read_token(&mut self, token: &str) -> Option<PgnGame> {
+        if matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*") {
+            self.game.termination = Some(token.to_string());
+            return self.finish_if_started();
+        }
+        if token.starts_with('$') {
+            return None; // numeric annotation glyph
+        }
+        let token = strip_move_number(token).trim_end_matches(['!', '?']);
+        if token.is_empty() {
+            return None;
+        }
+        // castling written with zeros, which SAN parsing doesn't accept
+        match token.starts_with("0-0") {
+            true => self.game.moves.push(token.replace('0', "O")),
+            false => self.game.moves.push(token.to_string()),
+        }
+        None
+    }
+
+    fn finish_if_started(&mut self) -> Option<PgnGame> {
+        if self.game.moves.is_empty() && self.game.termination.is_none() {
+            return None;
+        }
+        self.in_comment = false;
+        self.variation_depth = 0;
+        Some(std::mem::take(&mut self.game))
+    }
+
+    /// the last game, if the file doesn't end with a result
+    fn finish(mut self) -> Option<PgnGame> {
+        self.finish_if_started()
+    }
+}
+
+/// move numbers may stick to the move, like 1.e4 or 12...Nf6
+fn strip_move_number(token: &str) -> &str {
+    let without_digits = token.trim_start_matches(|c: char| c.is_ascii_digit());
+    match without_digits.len() < token.len() && without_digits.starts_with('.') {
+        true => without_digits.trim_start_matches('.'),
+        false => token,
+    }
+}
+
+fn parse_header(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.trim_end().strip_suffix(']')?;
+    let (name, value) = inner.split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((name.to_string(), value.replace("\\\"", "\"")))
+}
+
+/// outcomes of a move, from the point of view of the side playing it
+#[derive(Clone, Copy, Default)]
+struct MoveStats {
+    games: u32,
+    wins: u32,
+    draws: u32,
+}
+impl MoveStats {
+    fn weight(&self) -> u64 {
+        2 * self.wins as u64 + self.draws as u64
+    }
+
+    fn score_percent(&self) -> f64 {
+        self.weight() as f64 / (2 * self.games) as f64 * 100.0
+    }
+}
+
+#[derive(Default)]
+struct Collection {
+    moves: HashMap<(u64, Move), MoveStats>,
+    games: usize,
+    filtered: usize,
+    illegal: usize,
+}
+impl Collection {
+    fn add_game(&mut self, game: &PgnGame, options: &Options) {
+        let Some(result) = game.result().filter(|result| *result != "*") else {
+            self.filtered += 1;
+            return;
+        };
+        if !options.results.is_empty() && !options.results.iter().any(|r| r == result) {
+            self.filtered += 1;
+            return;
+        }
+        if let Some(min_rating) = options.min_rating {
+            let rating = |header| game.header(header).and_then(|r| r.parse::<u32>().ok());
+            match (rating("WhiteElo"), rating("BlackElo")) {
+                (Some(white), Some(black)) if white.min(black) >= min_rating => {}
+                _ => {
+                    self.filtered += 1;
+                    return;
+                }
+            }
+        }
+        // the book only makes sense for standard chess
+        if game
+            .header("Variant")
+            .is_some_and(|variant| !matches!(variant, "Standard" | "From Position"))
+        {
+            self.filtered += 1;
+            return;
+        }
+
+        let replayed = start_position(game).and_then(|mut position| {
+            let mut played = Vec::new();
+            for (ply, san) in game.moves.iter().take(options.max_ply).enumerate() {
+                let key = OpeningBook::key(&position);
+                let turn = position.turn();
+                let legal_move = util::play_move(&mut position, san)
+                    .with_context(|| format!("move {} ({san})", ply / 2 + 1))?;
+                played.push((key, legal_move, turn));
+            }
+            Ok(played)
+        });
+        let played = match replayed {
+            Ok(played) => played,
+            Err(e) => {
+                println!("Skipping illegal game {}: {e:#}", game.describe());
+                self.illegal += 1;
+                return;
+            }
+        };
+
+        self.games += 1;
+        for (key, legal_move, turn) in played {
+            let stats = self.moves.entry((key, legal_move)).or_default();
+            stats.games += 1;
+            match (result, turn) {
+                ("1/2-1/2", _) => stats.draws += 1,
+                ("1-0", Color::White) | ("0-1", Color::Black) => stats.wins += 1,
+                _ => {}
+            }
+        }
+    }
+}
+
+fn start_position(game: &PgnGame) -> Result<Chess> {
+    match game.header("FEN") {
+        Some(fen) => Ok(fen.parse::<Fen>()?.into_position(CastlingMode::Standard)?),
+        None => Ok(Chess::default()),
+    }
+}
+
+fn main() -> Result<()> {
+    let options = Options::parse(env::args().skip(1))?;
+    let start = Instant::now();
+
+    let mut collection = Collection::default();
+    for path in &options.inputs {
+        let file =
+            File::open(path).with_context(|| format!("could not open {}", path.display()))?;
+        let mut reader = PgnReader::default();
+        for line in BufReader::new(file).lines() {
+            let line = line.with_context(|| format!("could not read {}", path.display()))?;
+            if let Some(game) = reader.read_line(&line) {
+                collection.add_game(&game, &options);
+            }
+        }
+        if let Some(game) = reader.finish() {
+            collection.add_game(&game, &options);
+        }
+    }
+    println!(
+        "Read {} games in {:.1}s, skipped {} by the filters and {} illegal ones",
+        collection.games + collection.filtered + collection.illegal,
+        start.elapsed().as_secs_f32(),
+        collection.filtered,
+        collection.illegal
+    );
+
+    print_summary(&collection.moves);
+
+    let moves = collection
+        .moves
+        .into_iter()
+        .filter(|(_, stats)| stats.games >= options.min_games && stats.weight() > 0)
+        .collect::<Vec<_>>();
+    // Polyglot weights are 16 bit, only their ratio matters
+    let max_weight = moves.iter().map(|(_, stats)| stats.weight()).max();
+    let divisor = max_weight.map_or(1, |max| max.div_ceil(u16::MAX as u64));
+    let book = OpeningBook::from_moves(
+        options.output,
+        moves.into_iter().map(|((key, m), stats)| {
+            let weight = (stats.weight() / divisor).clamp(1, u16::MAX as u64) as u16;
+            (key, m, weight)
+        }),
+    );
+    book.save()?;
+    println!(
+        "Wrote {} moves to {}",
+        book.entry_count(),
+        book.path().display()
+    );
+    Ok(())
+}
+
+/// the most played first moves with how they scored
+fn print_summary(moves: &HashMap<(u64, Move), MoveStats>) {
+    let start_key = OpeningBook::key(&Chess::default());
+    let mut first_moves = moves
+        .iter()
+        .filter(|((key, _), _)| *key == start_key)
+        .map(|((_, m), stats)| (m, stats))
+        .collect::<Vec<_>>();
+    first_moves.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.games));
+    for (m, stats) in first_moves.into_iter().take(SUMMARY_MOVES) {
+        println!(
+            "  {m}: {} games, scored {:.1}%",
+            stats.games,
+            stats.score_percent()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_games(pgn: &str) -> Vec<PgnGame> {
+        let mut reader = PgnReader::default();
+        let mut games = pgn
+            .lines()
+            .filter_map(|line| reader.read_line(line))
+            .collect::<Vec<_>>();
+        games.extend(reader.finish());
+        games
+    }
+
+    #[test]
+    fn reads_moves_without_annotations() {
+        let games = read_games(
+            r#"[Event "First"]
+[White "A \"quoted\" name"]
+[Result "1-0"]
+
+1.e4 e5 {a comment
+over two lines} 2. Nf3 $1 Nc6 (2... d6 {inside} (2... f5) 3. d4) 3.Bc4!? Bc5 ; rest of the line
+4. 0-0 Nf6 5. d3 0-0 6... h6?? 1-0
+
+[Event "Second"]
+
+1. d4 d5 2. c4 dxc4 3. e3 O-O-O+ *
+[Event "Third"]
+1. e4"#,
+        );
+
+        assert_eq!(games.len(), 3);
+        assert_eq!(games[0].header("White"), Some("A \"quoted\" name"));
+        assert_eq!(games[0].result(), Some("1-0"));
+        assert_eq!(
+            games[0].moves,
+            [
+                "e4", "e5", "Nf3", "Nc6", "Bc4", "Bc5", "O-O", "Nf6", "d3", "O-O", "h6"
+            ]
+        );
+        assert_eq!(games[1].result(), Some("*"));
+        assert_eq!(games[1].moves, ["d4", "d5", "c4", "dxc4", "e3", "O-O-O+"]);
+        // the last game ends without a result
+        assert_eq!(games[2].result(), None);
+        assert_eq!(games[2].moves, ["e4"]);
+    }
+
+    #[test]
+    fn castling_with_zeros_replays() {
+        let games = read_games("1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. 0-0 Nf6 1/2-1/2");
+        let mut position = Chess::default();
+        for san in &games[0].moves {
+            util::play_move(&mut position, san).unwrap();
+        }
+        assert_eq!(position.fullmoves().get(), 5);
+    }
+}
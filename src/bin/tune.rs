@@ -0,0 +1,331 @@
+//! Texel tuning of the evaluation parameters: finds the piece values and strategy weights whose
+//! evaluations best predict the results of the games the positions were taken from.
+//!
+//! usage: tune <positions> <output>
+//!
+//! Every line of the positions file holds a FEN (or the four fields of an EPD) followed by the
+//! game result from white's point of view: `1-0`, `0-1`, `1/2-1/2`, `[1.0]`, `[0.5]`, `[0.0]`
+//! or an EPD opcode like `c9 "1-0";`. The positions should be quiet, they are evaluated
+//! statically. Endgames the evaluation scales down are left out, and so are the strategies that
+//! depend on the piece values (hanging and trapped pieces). The output is a parameter file
+//! for `BOT_PARAMS_FILE`. The current configuration (`BOT_PARAMS_FILE`, `BOT_STRATEGIES`) is the
+//! starting point.
+
+use std::{
+    env, fs,
+    io::{BufRead, BufReader},
+    thread,
+    time::Instant,
+};
+
+use anyhow::{Context, Result, bail};
+use rusty_lichess_bot::{
+    engine::{EngineConfig, Evaluator, Parameters, Score},
+    util,
+};
+use shakmaty::{CastlingMode, Chess, Position, Role, fen::Fen};
+
+/// the material strategy scales the piece values, tuning both would be redundant
+const MATERIAL_STRATEGY: &str = "material_difference";
+/// strategies computed from the piece values. Their raw scores would change with every step of
+/// the piece values, so they're left out of the model and keep their weights
+const PIECE_VALUE_STRATEGIES: [&str; 2] = ["hanging_pieces", "trapped_pieces"];
+/// the first step size tried for every parameter, halved whenever no step improves anything
+const INITIAL_STEP: i32 = 16;
+
+/// a position reduced to what the evaluation is linear in
+struct Sample {
+    /// 1.0 white won, 0.5 draw, 0.0 black won
+    result: f64,
+    /// white's minus black's number of pieces, pawn to queen
+    material: [i32; 5],
+    /// unweighted score of every tuned strategy, from white's point of view
+    strategies: Vec<i32>,
+}
+
+/// the parameters being tuned: piece values followed by strategy weights
+struct Model {
+    strategy_names: Vec<String>,
+    /// weights of the strategies that aren't tuned, written out as they are
+    fixed_weights: Vec<(String, i32)>,
+    /// weight of the material strategy, applied to the piece values
+    material_weight: i32,
+    values: Vec<i32>,
+}
+impl Model {
+    fn evaluate(&self, values: &[i32], sample: &Sample) -> f64 {
+        let (piece_values, weights) = values.split_at(5);
+        let material = sample
+            .material
+            .iter()
+            .zip(piece_values)
+            .map(|(count, value)| (count * value) as f64)
+            .sum::<f64>();
+        let strategies = sample
+            .strategies
+            .iter()
+            .zip(weights)
+            .map(|(raw, weight)| (raw * weight) as f64)
+            .sum::<f64>();
+        (material * self.material_weight as f64 + strategies) / 100.0
+    }
+
+    fn parameters(&self) -> Parameters {
+        let mut strategy_weights = vec![(MATERIAL_STRATEGY.to_string(), self.material_weight)];
+        strategy_weights.extend(
+            self.strategy_names
+                .iter()
+                .cloned()
+                .zip(self.values[5..].iter().copied()),
+        );
+        strategy_weights.extend(self.fixed_weights.iter().cloned());
+        Parameters {
+            piece_values: self.values[..5].try_into().expect("five piece values"),
+            strategy_weights,
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let args = env::args().collect::<Vec<_>>();
+    let [_, positions_path, output_path] = args.as_slice() else {
+        bail!("usage: tune <positions> <output>");
+    };
+
+    let config = EngineConfig::from_env();
+    // the evaluator's material sums and endgame scaling use them
+    util::set_piece_values(config.piece_values);
+    let mut evaluator = Evaluator::new(&config.strategies);
+
+    let start = Instant::now();
+    let (model, samples) = read_samples(positions_path, &mut evaluator, config.piece_values)?;
+    println!(
+        "Read {} positions in {:.1}s, tuning {} parameters",
+        samples.len(),
+        start.elapsed().as_secs_f32(),
+        model.values.len()
+    );
+    if samples.is_empty() {
+        bail!("no usable positions in {positions_path}");
+    }
+
+    let k = fit_scaling(&model, &samples);
+    println!(
+        "Scaling constant K = {k:.3}, error {:.6}",
+        error(&model, &model.values, &samples, k)
+    );
+    let model = tune(model, &samples, k);
+    let error = error(&model, &model.values, &samples, k);
+
+    let output = format!(
+        "# tuned on {} positions, mean squared error {error:.6} (K = {k:.3})\n{}",
+        samples.len(),
+        model.parameters()
+    );
+    fs::write(output_path, output).with_context(|| format!("could not write {output_path}"))?;
+    println!("Wrote the tuned parameters to {output_path}");
+    Ok(())
+}
+
+fn read_samples(
+    path: &str,
+    evaluator: &mut Evaluator,
+    piece_values: [i32; 5],
+) -> Result<(Model, Vec<Sample>)> {
+    let file = fs::File::open(path).with_context(|| format!("could not open {path}"))?;
+    let mut model = None;
+    let mut samples = Vec::new();
+    let mut skipped = 0;
+
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((position, result)) = parse_line(&line) else {
+            skipped += 1;
+            continue;
+        };
+
+        let scores = evaluator.explain(&position);
+        // mates and draws aren't up to the parameters, and the model can't express endgames
+        // that scale the whole evaluation
+        if scores
+            .iter()
+            .any(|score| !matches!(score.raw, Score::Centipawns(_)))
+            || evaluator.scale(&position) < 100
+        {
+            skipped += 1;
+            continue;
+        }
+        model.get_or_insert_with(|| {
+            let tuned = scores
+                .iter()
+                .filter(|score| is_tuned(score.name))
+                .collect::<Vec<_>>();
+            Model {
+                strategy_names: tuned.iter().map(|score| score.name.to_string()).collect(),
+                fixed_weights: scores
+                    .iter()
+                    .filter(|score| PIECE_VALUE_STRATEGIES.contains(&score.name))
+                    .map(|score| (score.name.to_string(), score.weight))
+                    .collect(),
+                material_weight: scores
+                    .iter()
+                    .find(|score| score.name == MATERIAL_STRATEGY)
+                    .map_or(0, |score| score.weight),
+                values: piece_values
+                    .into_iter()
+                    .chain(tuned.iter().map(|score| score.weight))
+                    .collect(),
+            }
+        });
+
+        let side = position.turn().fold_wb(1, -1);
+        let board = position.board();
+        let material = [
+            Role::Pawn,
+            Role::Knight,
+            Role::Bishop,
+            Role::Rook,
+            Role::Queen,
+        ]
+        .map(|role| {
+            let pieces = board.by_role(role);
+            (pieces & board.white()).count() as i32 - (pieces & board.black()).count() as i32
+        });
+        let strategies = scores
+            .iter()
+            .filter(|score| is_tuned(score.name))
+            .map(|score| match score.raw {
+                Score::Centipawns(centipawns) => side * centipawns,
+                _ => 0,
+            })
+            .collect();
+        samples.push(Sample {
+            result,
+            material,
+            strategies,
+        });
+    }
+
+    if skipped > 0 {
+        println!("Skipped {skipped} lines (unreadable, mates, draws or scaled endgames)");
+    }
+    let model = model.unwrap_or(Model {
+        strategy_names: Vec::new(),
+        fixed_weights: Vec::new(),
+        material_weight: 0,
+        values: piece_values.to_vec(),
+    });
+    Ok((model, samples))
+}
+
+/// strategy weights the tuner changes, besides the piece values
+fn is_tuned(strategy: &str) -> bool {
+    strategy != MATERIAL_STRATEGY && !PIECE_VALUE_STRATEGIES.contains(&strategy)
+}
+
+/// the position and the result, from white's point of view
+fn parse_line(line: &str) -> Option<(Chess, f64)> {
+    let fields = line.split_whitespace().collect::<Vec<_>>();
+    // a full FEN has 6 fields, an EPD only the first 4
+    let (position, rest) = [6, 4].into_iter().find_map(|length| {
+        let fen = fields.get(..length)?.join(" ");
+        let position = fen
+            .parse::<Fen>()
+            .ok()?
+            .into_position::<Chess>(CastlingMode::Standard)
+            .ok()?;
+        Some((position, &fields[length..]))
+    })?;
+
+    let result = rest.iter().find_map(|field| {
+        match field.trim_matches(|c| matches!(c, '"' | '[' | ']' | ';')) {
+            "1-0" | "1.0" => Some(1.0),
+            "0-1" | "0.0" => Some(0.0),
+            "1/2-1/2" | "0.5" => Some(0.5),
+            _ => None,
+        }
+    })?;
+    Some((position, result))
+}
+
+/// the K in 1 / (1 + 10^(-K * eval / 400)) that fits the results best with the initial
+/// parameters, so the tuning doesn't just rescale everything
+fn fit_scaling(model: &Model, samples: &[Sample]) -> f64 {
+    let (mut low, mut high) = (0.0, 10.0);
+    // ternary search, the error is convex in K
+    for _ in 0..100 {
+        let lower_third = low + (high - low) / 3.0;
+        let upper_third = high - (high - low) / 3.0;
+        if error(model, &model.values, samples, lower_third)
+            < error(model, &model.values, samples, upper_third)
+        {
+            high = upper_third;
+        } else {
+            low = lower_third;
+        }
+    }
+    (low + high) / 2.0
+}
+
+/// local search: tries moving every parameter up and down by the step size and keeps whatever
+/// lowers the error, until even the smallest step doesn't help anymore
+fn tune(mut model: Model, samples: &[Sample], k: f64) -> Model {
+    let mut best_error = error(&model, &model.values, samples, k);
+    let mut step = INITIAL_STEP;
+    let mut iteration = 0;
+    while step > 0 {
+        iteration += 1;
+        let mut improved = false;
+        for index in 0..model.values.len() {
+            for delta in [step, -step] {
+                let mut candidate = model.values.clone();
+                candidate[index] = (candidate[index] + delta).max(0);
+                if candidate[index] == model.values[index] {
+                    continue;
+                }
+                let candidate_error = error(&model, &candidate, samples, k);
+                if candidate_error < best_error {
+                    best_error = candidate_error;
+                    model.values = candidate;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        println!("Iteration {iteration} (step {step}): error {best_error:.6}");
+        if !improved {
+            step /= 2;
+        }
+    }
+    model
+}
+
+/// mean squared difference between the results and the results predicted from the evaluations
+fn error(model: &Model, values: &[i32], samples: &[Sample], k: f64) -> f64 {
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_size = samples.len().div_ceil(threads).max(1);
+    let total = thread::scope(|scope| {
+        samples
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|sample| {
+                            let eval = model.evaluate(values, sample);
+                            let predicted = 1.0 / (1.0 + 10f64.powf(-k * eval / 400.0));
+                            (sample.result - predicted).powi(2)
+                        })
+                        .sum::<f64>()
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().expect("error computation panicked"))
+            .sum::<f64>()
+    });
+    total / samples.len() as f64
+}
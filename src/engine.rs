@@ -1,21 +1,103 @@
+mod accumulator;
+mod book;
+mod book_learning;
+mod config;
+mod endgame;
+mod king_safety;
 mod main_engine;
+mod move_ordering;
+mod nnue;
+mod parameters;
+mod pawn_structure;
+mod piece_activity;
+mod piece_square;
 mod random_engine;
+mod score;
+mod see;
+mod strategy;
+mod tablebase;
+mod thread_budget;
+mod time_manager;
+mod transposition;
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
 
 use anyhow::Result;
 use async_trait::async_trait;
+pub use book::OpeningBook;
+pub use config::EngineConfig;
 use main_engine::MainEngine;
-use shakmaty::{Chess, Color, Move, uci::UciMove};
+pub use parameters::Parameters;
+pub use score::Score;
+use shakmaty::{Chess, Color, KnownOutcome, Move, uci::UciMove};
+pub use strategy::{Evaluator, StrategyScore, StrategySettings};
+pub use time_manager::GameClock;
 
-pub fn init_engine(initial_position: Chess, bot_color: Color) -> Box<dyn Engine> {
-    let engine = MainEngine::new(initial_position, bot_color);
+pub fn init_engine(
+    initial_position: Chess,
+    bot_color: Color,
+    config: EngineConfig,
+) -> Box<dyn Engine> {
+    let engine = MainEngine::new(initial_position, bot_color, config);
     Box::new(engine)
 }
 
+/// outcome of a search: the move to play and the line the engine expects to follow
+pub struct SearchResult {
+    pub best_move: Move,
+    /// evaluation from the engine's point of view
+    pub eval: Score,
+    /// principal variation, starting with `best_move`
+    pub principal_variation: Vec<Move>,
+}
+
+/// cancellation token shared between the game loop and a running search. A stopped search
+/// returns the best move it has found so far.
+#[derive(Clone, Default)]
+pub struct StopSignal(Arc<AtomicBool>);
+impl StopSignal {
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
 #[async_trait]
 pub trait Engine: Send + Sync {
     async fn update_board(&mut self, move_played: UciMove) -> Result<()>;
 
-    async fn search(&mut self) -> Option<Move>;
+    /// keep the engine informed about the remaining time, so it can budget its searches
+    fn update_clock(&mut self, clock: GameClock);
+
+    /// to be called right before `search`: clears a stop from an earlier search. Kept out of
+    /// the search future, which may not run until after the caller already stopped it again
+    fn prepare_search(&mut self);
+
+    async fn search(&mut self) -> Option<SearchResult>;
+
+    /// keep searching while the opponent is thinking, assuming they play the expected reply.
+    /// The next `update_board` decides whether that work can be reused.
+    fn start_pondering(&mut self);
+
+    /// handle to stop a running search from the outside
+    fn stop_signal(&self) -> StopSignal;
+
+    /// what each evaluation strategy thinks of the position, from the perspective of the side to
+    /// move. Meant for debugging why a move was chosen
+    fn explain(&self, position: &Chess) -> Vec<StrategyScore>;
+
+    /// the game is over, with the given result
+    fn game_over(&mut self, outcome: KnownOutcome);
 
     fn get_game_state(&self) -> &Chess;
 
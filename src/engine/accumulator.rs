@@ -0,0 +1,118 @@
+use shakmaty::{Board, Color, Move, Piece, Role, Square};
+
+use crate::util;
+
+use super::piece_square;
+
+/// material and piece-square sums of a position, from white's perspective. The search derives
+/// them move by move from the parent position instead of recomputing them from the whole board
+/// at every leaf
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Accumulator {
+    material: i32,
+    middlegame: i32,
+    endgame: i32,
+    /// not capped at piece_square::MAX_PHASE, so promotions can be undone by captures
+    phase: i32,
+}
+impl Accumulator {
+    /// the full recomputation, for the root of a search and to verify the incremental updates
+    pub fn new(board: &Board) -> Self {
+        let mut accumulator = Self {
+            material: util::material_difference(board),
+            middlegame: 0,
+            endgame: 0,
+            phase: 0,
+        };
+        for (square, piece) in board.iter() {
+            accumulator.update_positional(piece, square, 1);
+        }
+        accumulator
+    }
+
+    /// the accumulator of the position after the given side played the move
+    pub fn play(mut self, turn: Color, m: &Move) -> Self {
+        piece_changes(turn, m, |piece, square, direction| {
+            if direction > 0 {
+                self.add(piece, square);
+            } else {
+                self.remove(piece, square);
+            }
+        });
+        self
+    }
+
+    /// material balance from the perspective of the given side
+    pub fn material(&self, color: Color) -> i32 {
+        color.fold_wb(self.material, -self.material)
+    }
+
+    /// piece-square tables from the perspective of the given side, tapered by the game phase
+    pub fn piece_square(&self, color: Color) -> i32 {
+        let phase = self.phase.min(piece_square::MAX_PHASE);
+        let white_score = piece_square::taper(self.middlegame, self.endgame, phase);
+        color.fold_wb(white_score, -white_score)
+    }
+
+    fn add(&mut self, piece: Piece, square: Square) {
+        self.material += piece.color.fold_wb(1, -1) * util::piece_value(piece.role);
+        self.update_positional(piece, square, 1);
+    }
+
+    fn remove(&mut self, piece: Piece, square: Square) {
+        self.material -= piece.color.fold_wb(1, -1) * util::piece_value(piece.role);
+        self.update_positional(piece, square, -1);
+    }
+
+    /// direction 1 for a piece arriving on the square, -1 for one leaving it
+    fn update_positional(&mut self, piece: Piece, square: Square, direction: i32) {
+        let (middlegame, endgame) = piece_square::value(piece, square);
+        let side = piece.color.fold_wb(direction, -direction);
+        self.middlegame += side * middlegame;
+        self.endgame += side * endgame;
+        self.phase += direction * piece_square::phase_weight(piece.role);
+    }
+}
+
+/// calls back with every piece leaving (-1) or arriving on (1) a square when the given side plays
+/// the move. Pieces leave before others arrive
+pub fn piece_changes(turn: Color, m: &Move, mut change: impl FnMut(Piece, Square, i32)) {
+    let own = |role| Piece { color: turn, role };
+    let opponent = |role| Piece {
+        color: turn.other(),
+        role,
+    };
+    match *m {
+        Move::Normal {
+            role,
+            from,
+            capture,
+            to,
+            promotion,
+        } => {
+            if let Some(captured) = capture {
+                change(opponent(captured), to, -1);
+            }
+            change(own(role), from, -1);
+            change(own(promotion.unwrap_or(role)), to, 1);
+        }
+        Move::EnPassant { from, to } => {
+            // the captured pawn isn't on the target square
+            change(
+                opponent(Role::Pawn),
+                Square::from_coords(to.file(), from.rank()),
+                -1,
+            );
+            change(own(Role::Pawn), from, -1);
+            change(own(Role::Pawn), to, 1);
+        }
+        Move::Castle { king, rook } => {
+            let side = m.castling_side().expect("castling move has a side");
+            change(own(Role::King), king, -1);
+            change(own(Role::Rook), rook, -1);
+            change(own(Role::King), side.king_to(turn), 1);
+            change(own(Role::Rook), side.rook_to(turn), 1);
+        }
+        Move::Put { role, to } => change(own(role), to, 1),
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    cmp::Reverse,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, ensure};
+use rand::{Rng, rng};
+use shakmaty::{Chess, Move, Position};
+
+use super::{
+    book_learning::{LearnedResult, LearningStore},
+    transposition,
+};
+
+/// size of one entry in a Polyglot file
+const ENTRY_SIZE: usize = 16;
+
+/// an opening book in the Polyglot format: entries of 16 big-endian bytes (position key u64,
+/// move u16, weight u16, learn u32), sorted by key. The keys are the Zobrist keys of
+/// `transposition::position_key`, shakmaty uses the Polyglot random numbers for those
+pub struct OpeningBook {
+    path: PathBuf,
+    entries: Vec<BookEntry>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct BookEntry {
+    key: u64,
+    /// to file in bits 0-2, to rank 3-5, from file 6-8, from rank 9-11, promotion 12-14
+    raw_move: u16,
+    weight: u16,
+}
+
+/// a move found in the book
+pub struct BookMove {
+    pub book_move: Move,
+    pub weight: u16,
+    /// weight of all playable book moves in the position
+    pub total_weight: u32,
+    /// how the move worked out in earlier games
+    pub learned: Option<LearnedResult>,
+}
+impl fmt::Display for BookMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (weight {} of {}",
+            self.book_move, self.weight, self.total_weight
+        )?;
+        if let Some(learned) = self.learned {
+            write!(f, ", learned {learned}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl OpeningBook {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).with_context(|| format!("could not read {}", path.display()))?;
+        ensure!(
+            bytes.len() % ENTRY_SIZE == 0,
+            "invalid book file {}: size is no multiple of {ENTRY_SIZE} bytes",
+            path.display()
+        );
+        let mut entries = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| BookEntry {
+                key: u64::from_be_bytes(entry[0..8].try_into().unwrap()),
+                raw_move: u16::from_be_bytes([entry[8], entry[9]]),
+                weight: u16::from_be_bytes([entry[10], entry[11]]),
+            })
+            .collect::<Vec<_>>();
+        // lookups rely on the order, a sloppily written book shouldn't break them
+        entries.sort_by_key(|entry| entry.key);
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// a book with the given moves by position key, to be saved to the path
+    pub fn from_moves(path: PathBuf, moves: impl IntoIterator<Item = (u64, Move, u16)>) -> Self {
+        let mut entries = moves
+            .into_iter()
+            .map(|(key, m, weight)| BookEntry {
+                key,
+                raw_move: encode_move(&m),
+                weight,
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|entry| (entry.key, Reverse(entry.weight)));
+        Self { path, entries }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let bytes = self
+            .entries
+            .iter()
+            .flat_map(|entry| {
+                let mut bytes = [0; ENTRY_SIZE];
+                bytes[0..8].copy_from_slice(&entry.key.to_be_bytes());
+                bytes[8..10].copy_from_slice(&entry.raw_move.to_be_bytes());
+                bytes[10..12].copy_from_slice(&entry.weight.to_be_bytes());
+                // the learn field stays 0
+                bytes
+            })
+            .collect::<Vec<_>>();
+        fs::write(&self.path, bytes)
+            .with_context(|| format!("could not write {}", self.path.display()))
+    }
+
+    /// the Polyglot key of the position
+    pub fn key(position: &Chess) -> u64 {
+        transposition::position_key(position)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// the legal book moves of the position with their weights. Moves with weight 0 are in the
+    /// book to be avoided and left out
+    pub fn moves(&self, position: &Chess) -> Vec<(Move, u16)> {
+        let key = Self::key(position);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let legal_moves = position.legal_moves();
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter(|entry| entry.weight > 0)
+            .filter_map(|entry| {
+                legal_moves
+                    .iter()
+                    .find(|m| encode_move(m) == entry.raw_move)
+                    .map(|m| (*m, entry.weight))
+            })
+            .collect()
+    }
+
+    /// picks one of the book moves at random, the chance of a move growing with its weight and
+    /// with how well it did in the bot's earlier games. The temperature flattens (above 1) or
+    /// sharpens (below 1) the weights, at 0 the move with the highest weight is always played
+    pub fn pick(
+        &self,
+        position: &Chess,
+        temperature: f64,
+        learning: Option<&LearningStore>,
+    ) -> Option<BookMove> {
+        let key = Self::key(position);
+        let moves = self
+            .moves(position)
+            .into_iter()
+            .map(|(m, weight)| {
+                let learned = learning.and_then(|learning| learning.result(key, &m));
+                (m, weight, learned)
+            })
+            .collect::<Vec<_>>();
+        let total_weight = moves.iter().map(|(_, weight, _)| *weight as u32).sum();
+        let adjusted_weight = |(_, weight, learned): &(Move, u16, Option<LearnedResult>)| {
+            *weight as f64 * learned.map_or(1.0, |learned| learned.factor())
+        };
+
+        let (book_move, weight, learned) = if temperature <= 0.0 {
+            *moves
+                .iter()
+                .max_by(|a, b| adjusted_weight(a).total_cmp(&adjusted_weight(b)))?
+        } else {
+            let chances = moves
+                .iter()
+                .map(|m| adjusted_weight(m).powf(1.0 / temperature))
+                .collect::<Vec<_>>();
+            let mut target = rng().random::<f64>() * chances.iter().sum::<f64>();
+            let index = chances
+                .iter()
+                .position(|chance| {
+                    target -= chance;
+                    target < 0.0
+                })
+                // rounding can leave a tiny rest
+                .unwrap_or(moves.len().checked_sub(1)?);
+            moves[index]
+        };
+        Some(BookMove {
+            book_move,
+            weight,
+            total_weight,
+            learned,
+        })
+    }
+}
+
+/// the Polyglot encoding of a move. Castling is encoded as the king capturing its own rook,
+/// promotions count from knight (1) to queen (4)
+fn encode_move(m: &Move) -> u16 {
+    let (from, to) = match *m {
+        Move::Castle { king, rook } => (king, rook),
+        _ => match m.from() {
+            Some(from) => (from, m.to()),
+            // drops don't exist in standard chess
+            None => return 0,
+        },
+    };
+    let promotion = m.promotion().map_or(0, |role| role as u16 - 1);
+    promotion << 12
+        | (from.rank() as u16) << 9
+        | (from.file() as u16) << 6
+        | (to.rank() as u16) << 3
+        | to.file() as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use shakmaty::{CastlingMode, Square};
+
+    use super::*;
+    use crate::util;
+
+    const START_KEY: u64 = 0x463b96181691fc9c;
+
+    fn entry(key: u64, raw_move: u16, weight: u16) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&raw_move.to_be_bytes());
+        bytes[10..12].copy_from_slice(&weight.to_be_bytes());
+        bytes
+    }
+
+    /// the position after 1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5, white can castle
+    fn italian() -> Chess {
+        let mut position = Chess::default();
+        for san in ["e4", "e5", "Nf3", "Nc6", "Bc4", "Bc5"] {
+            util::play_move(&mut position, san).unwrap();
+        }
+        position
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{name}-{}.bin", std::process::id()))
+    }
+
+    #[test]
+    fn reads_polyglot_entries() {
+        assert_eq!(OpeningBook::key(&Chess::default()), START_KEY);
+        let italian = italian();
+        // written out of order, with a move to avoid (weight 0)
+        let bytes = [
+            entry(OpeningBook::key(&italian), 0x0107, 5), // e1h1, castling short
+            entry(START_KEY, 0x031c, 30),                 // e2e4
+            entry(START_KEY, 0x02db, 10),                 // d2d4
+            entry(START_KEY, 0x0195, 0),                  // g1f3
+        ]
+        .concat();
+        let path = temp_path("polyglot-read");
+        fs::write(&path, bytes).unwrap();
+        let book = OpeningBook::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let moves = book.moves(&Chess::default());
+        let uci = moves
+            .iter()
+            .map(|(m, weight)| (m.to_uci(CastlingMode::Standard).to_string(), *weight))
+            .collect::<Vec<_>>();
+        assert_eq!(uci, [("e2e4".to_string(), 30), ("d2d4".to_string(), 10)]);
+
+        let castling = book.moves(&italian);
+        assert_eq!(castling.len(), 1);
+        assert_eq!(
+            castling[0].0,
+            Move::Castle {
+                king: Square::E1,
+                rook: Square::H1
+            }
+        );
+    }
+
+    #[test]
+    fn saved_books_load_the_same() {
+        let italian = italian();
+        let castle = Move::Castle {
+            king: Square::E1,
+            rook: Square::H1,
+        };
+        let e4 = Chess::default()
+            .legal_moves()
+            .into_iter()
+            .find(|m| m.to() == Square::E4)
+            .unwrap();
+        let path = temp_path("polyglot-round-trip");
+        let book = OpeningBook::from_moves(
+            path.clone(),
+            [(OpeningBook::key(&italian), castle, 7), (START_KEY, e4, 3)],
+        );
+        book.save().unwrap();
+        let loaded = OpeningBook::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.entries, book.entries);
+        assert_eq!(loaded.moves(&Chess::default()), [(e4, 3)]);
+        assert_eq!(loaded.moves(&italian), [(castle, 7)]);
+    }
+}
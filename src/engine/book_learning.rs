@@ -0,0 +1,196 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result, bail};
+use shakmaty::{CastlingMode, Color, KnownOutcome, Move};
+
+/// games of different engines finish at the same time, their updates must not overwrite each
+/// other
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// how the book moves the bot played worked out, from the bot's point of view
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LearnedResult {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+impl LearnedResult {
+    /// what the book weight of the move is multiplied with: 1 without any games, approaching 2
+    /// for a move that keeps winning and 0 for one that keeps losing
+    pub fn factor(&self) -> f64 {
+        let games = (self.wins + self.draws + self.losses) as f64;
+        let points = 2.0 * self.wins as f64 + self.draws as f64;
+        // as if the move had scored a win and a loss already, so single games don't count much
+        (points + 2.0) / (2.0 * games + 4.0) * 2.0
+    }
+}
+impl fmt::Display for LearnedResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "+{} ={} -{}", self.wins, self.draws, self.losses)
+    }
+}
+
+/// results of the games the bot played book moves in, by position key and move. A text file
+/// with one line per move, `<key in hex> <uci move> <wins> <draws> <losses>`, e.g.
+/// `463b96181691fc9c e2e4 3 1 2`. Deleting the file resets the learning
+pub struct LearningStore {
+    path: PathBuf,
+    results: HashMap<(u64, String), LearnedResult>,
+}
+impl LearningStore {
+    /// an empty store if the file doesn't exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("could not read {}", path.display())),
+        };
+        let mut results = HashMap::new();
+        for (number, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (key, result) = parse_line(line)
+                .with_context(|| format!("line {}", number + 1))
+                .with_context(|| format!("invalid learning file {}", path.display()))?;
+            results.insert(key, result);
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            results,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn result(&self, key: u64, m: &Move) -> Option<LearnedResult> {
+        self.results.get(&(key, uci(m))).copied()
+    }
+
+    /// adds the outcome of a game to every book move the bot played in it and writes the
+    /// file. Reloads it first, other games may have finished in the meantime
+    pub fn record(
+        &mut self,
+        book_moves: &[(u64, Move)],
+        outcome: KnownOutcome,
+        bot_color: Color,
+    ) -> Result<()> {
+        let _lock = FILE_LOCK.lock().unwrap();
+        *self = Self::load(&self.path)?;
+        for (key, m) in book_moves {
+            let result = self.results.entry((*key, uci(m))).or_default();
+            match outcome {
+                KnownOutcome::Draw => result.draws += 1,
+                KnownOutcome::Decisive { winner } if winner == bot_color => result.wins += 1,
+                KnownOutcome::Decisive { .. } => result.losses += 1,
+            }
+        }
+
+        let mut lines = self
+            .results
+            .iter()
+            .map(|((key, m), result)| {
+                format!(
+                    "{key:016x} {m} {} {} {}\n",
+                    result.wins, result.draws, result.losses
+                )
+            })
+            .collect::<Vec<_>>();
+        // stable order, so the file can be compared between games
+        lines.sort();
+        fs::write(&self.path, lines.concat())
+            .with_context(|| format!("could not write {}", self.path.display()))
+    }
+}
+
+fn parse_line(line: &str) -> Result<((u64, String), LearnedResult)> {
+    let fields = line.split_whitespace().collect::<Vec<_>>();
+    let [key, m, wins, draws, losses] = fields.as_slice() else {
+        bail!("expected <key> <move> <wins> <draws> <losses>");
+    };
+    let key = u64::from_str_radix(key, 16).context("invalid key")?;
+    let result = LearnedResult {
+        wins: wins.parse().context("invalid number of wins")?,
+        draws: draws.parse().context("invalid number of draws")?,
+        losses: losses.parse().context("invalid number of losses")?,
+    };
+    Ok(((key, m.to_string()), result))
+}
+
+/// castling as the king's move, like lichess shows it
+fn uci(m: &Move) -> String {
+    m.to_uci(CastlingMode::Standard).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use shakmaty::{Chess, Position, Square};
+
+    use super::*;
+
+    #[test]
+    fn recorded_results_load_again() {
+        let path = std::env::temp_dir().join(format!("book-learning-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let start = Chess::default();
+        let move_to = |square| {
+            start
+                .legal_moves()
+                .into_iter()
+                .find(|m| m.to() == square)
+                .unwrap()
+        };
+        let (e4, d4) = (move_to(Square::E4), move_to(Square::D4));
+        let castle = Move::Castle {
+            king: Square::E1,
+            rook: Square::H1,
+        };
+        let book_moves = [(0x463b96181691fc9c, e4), (0x1234, castle)];
+
+        let mut store = LearningStore::load(&path).unwrap();
+        let white_wins = KnownOutcome::Decisive {
+            winner: Color::White,
+        };
+        store.record(&book_moves, white_wins, Color::White).unwrap();
+        store
+            .record(&book_moves[..1], KnownOutcome::Draw, Color::White)
+            .unwrap();
+        store
+            .record(&book_moves[..1], white_wins, Color::Black)
+            .unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        let loaded = LearningStore::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            content,
+            "0000000000001234 e1g1 1 0 0\n463b96181691fc9c e2e4 1 1 1\n"
+        );
+        let expected = LearnedResult {
+            wins: 1,
+            draws: 1,
+            losses: 1,
+        };
+        assert_eq!(loaded.result(0x463b96181691fc9c, &e4), Some(expected));
+        assert_eq!(loaded.result(0x1234, &castle).map(|r| r.wins), Some(1));
+        assert_eq!(loaded.result(0x463b96181691fc9c, &d4), None);
+    }
+
+    #[test]
+    fn invalid_lines_are_an_error() {
+        for line in [
+            "463b96181691fc9c e2e4 1 0",
+            "xyz e2e4 1 0 0",
+            "1234 e2e4 1 -1 0",
+        ] {
+            assert!(parse_line(line).is_err(), "{line}");
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use log::warn;
+
+use super::{parameters::Parameters, strategy::StrategySettings, thread_budget};
+
+/// tunable engine settings. Every value can be overridden by an environment variable of the
+/// same name in upper case, prefixed with `BOT_` (e.g. `BOT_HASH_SIZE_MB=256`). Tuned
+/// evaluation parameters are loaded from the file in `BOT_PARAMS_FILE`, `BOT_STRATEGIES` still
+/// takes precedence over the strategy weights in there
+#[derive(Clone, Debug)]
+pub struct EngineConfig {
+    /// size of the transposition table in megabytes
+    pub hash_size_mb: usize,
+    /// also search quiet checking moves at the first ply of the quiescence search
+    pub quiescence_checks: bool,
+    /// prune positions where passing still fails high
+    pub null_move_pruning: bool,
+    /// search late quiet moves with reduced depth
+    pub late_move_reductions: bool,
+    /// maximum number of threads per search (lazy SMP). With several games at the same time,
+    /// each gets an equal share of the cores at most, and fewer while the others are searching.
+    pub threads: usize,
+    /// keep searching on the opponent's time with one thread, assuming they play the expected
+    /// reply
+    pub ponder: bool,
+    /// how much worse than equal (in centipawns) the engine considers a draw by repetition or
+    /// the fifty-move rule. Negative values make it seek draws.
+    pub contempt: i32,
+    /// weights of the evaluation strategies and which of them are enabled, e.g.
+    /// `mobility=50,outposts=off,chaaaaaaarge=on`. Unlisted strategies keep their defaults
+    pub strategies: StrategySettings,
+    /// network file (see `Network::load` for the format) to evaluate positions with instead
+    /// of the strategies. Without one, or if it can't be loaded, the strategies are used
+    pub nnue_file: Option<PathBuf>,
+    /// pawn, knight, bishop, rook, queen. Global for the whole process, `util::set_piece_values`
+    /// has to be called with them once at startup
+    pub piece_values: [i32; 5],
+    /// Polyglot opening book (`.bin`) to play from before searching
+    pub book_file: Option<PathBuf>,
+    /// randomness of the book moves: 1 picks them in proportion to their weights, higher
+    /// values even the chances out, 0 always plays the move with the highest weight
+    pub book_temperature: f64,
+    /// remember how the book moves worked out and prefer the ones that scored well
+    pub book_learning: bool,
+    /// where the results of the book moves are kept, see `LearningStore`
+    pub book_learning_file: PathBuf,
+    /// directory with Syzygy tablebase files. The root move is taken from them and the search
+    /// scores the positions they cover exactly
+    pub syzygy_path: Option<PathBuf>,
+}
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            hash_size_mb: 64,
+            quiescence_checks: false,
+            null_move_pruning: true,
+            late_move_reductions: true,
+            threads: thread_budget::available_cores(),
+            ponder: false,
+            contempt: 0,
+            strategies: StrategySettings::default(),
+            nnue_file: None,
+            piece_values: Parameters::default().piece_values,
+            book_file: None,
+            book_temperature: 1.0,
+            book_learning: true,
+            book_learning_file: PathBuf::from("book_learning.txt"),
+            syzygy_path: None,
+        }
+    }
+}
+impl EngineConfig {
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let parameters = match env::var("BOT_PARAMS_FILE") {
+            Ok(path) => Parameters::load(Path::new(&path)).unwrap_or_else(|e| {
+                warn!("{e:#}, using the default evaluation parameters");
+                Parameters::default()
+            }),
+            Err(_) => Parameters::default(),
+        };
+        Self {
+            hash_size_mb: env_or("BOT_HASH_SIZE_MB", default.hash_size_mb),
+            quiescence_checks: env_or("BOT_QUIESCENCE_CHECKS", default.quiescence_checks),
+            null_move_pruning: env_or("BOT_NULL_MOVE_PRUNING", default.null_move_pruning),
+            late_move_reductions: env_or("BOT_LATE_MOVE_REDUCTIONS", default.late_move_reductions),
+            threads: env_or("BOT_THREADS", default.threads).max(1),
+            ponder: env_or("BOT_PONDER", default.ponder),
+            contempt: env_or("BOT_CONTEMPT", default.contempt),
+            strategies: parameters
+                .strategy_settings()
+                .overridden_by(env_or("BOT_STRATEGIES", default.strategies)),
+            nnue_file: env::var("BOT_NNUE_FILE").ok().map(PathBuf::from),
+            piece_values: parameters.piece_values,
+            book_file: env::var("BOT_BOOK_FILE").ok().map(PathBuf::from),
+            book_temperature: env_or("BOT_BOOK_TEMPERATURE", default.book_temperature).max(0.0),
+            book_learning: env_or("BOT_BOOK_LEARNING", default.book_learning),
+            book_learning_file: env_or("BOT_BOOK_LEARNING_FILE", default.book_learning_file),
+            syzygy_path: env::var("BOT_SYZYGY_PATH").ok().map(PathBuf::from),
+        }
+    }
+}
+
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    match env::var(key) {
+        Ok(value) => value.trim().parse().unwrap_or_else(|_| {
+            warn!("Ignoring invalid value '{value}' for {key}");
+            default
+        }),
+        Err(_) => default,
+    }
+}
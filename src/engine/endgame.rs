@@ -0,0 +1,248 @@
+use shakmaty::{Bitboard, Board, ByRole, Color, File, Rank, Role, Square};
+
+use crate::util;
+
+/// per step the lone king is pushed towards the edge (or the right corner)
+const EDGE_BONUS: i32 = 10;
+/// per step the attacking king gets closer to the lone king
+const KING_PROXIMITY_BONUS: i32 = 4;
+/// share of the evaluation left, in percent, when the side ahead has no pawns and only a minor
+/// piece or at most a minor piece more, like rook against bishop
+const NO_PAWNS_SCALE: i32 = 25;
+/// with only opposite-colored bishops and pawns, and up to one pawn more than the opponent
+const OPPOSITE_BISHOPS_SCALE: i32 = 25;
+/// with only opposite-colored bishops and pawns, but more than one pawn up
+const OPPOSITE_BISHOPS_MANY_PAWNS_SCALE: i32 = 50;
+/// with opposite-colored bishops and one rook each besides the pawns
+const OPPOSITE_BISHOPS_ROOKS_SCALE: i32 = 75;
+
+/// bonus for driving a bare king to the edge and walking the own king up to it, which is how
+/// the basic mates work. Without it, the search rarely sees far enough to make progress. From
+/// the perspective of the given side
+pub fn mop_up(board: &Board, color: Color) -> i32 {
+    mop_up_for(board, color) - mop_up_for(board, color.other())
+}
+
+fn mop_up_for(board: &Board, strong: Color) -> i32 {
+    let weak = strong.other();
+    let lone_king = (board.by_color(weak) & !board.kings()).is_empty();
+    let (Some(strong_king), Some(weak_king)) = (board.king_of(strong), board.king_of(weak)) else {
+        return 0;
+    };
+    if !lone_king || !can_force_mate(board, strong) {
+        return 0;
+    }
+
+    let material = board.material_side(strong);
+    let edge = if is_bishop_and_knight(&material) {
+        // only the corners of the bishop's color are mating squares
+        let corners =
+            match (board.bishops() & board.by_color(strong) & Bitboard::DARK_SQUARES).any() {
+                true => [Square::A1, Square::H8],
+                false => [Square::A8, Square::H1],
+            };
+        let corner_distance = corners
+            .iter()
+            .map(|corner| manhattan_distance(weak_king, *corner))
+            .min()
+            .unwrap_or(0);
+        14 - corner_distance
+    } else {
+        center_distance(weak_king)
+    };
+    let proximity = 14 - manhattan_distance(strong_king, weak_king);
+    edge * EDGE_BONUS + proximity * KING_PROXIMITY_BONUS
+}
+
+/// share of the evaluation that is left, in percent, for material signatures that are drawn
+/// or hard to win despite what the material says. The same for both sides
+pub fn scale(board: &Board) -> i32 {
+    let white = board.material_side(Color::White);
+    let black = board.material_side(Color::Black);
+    let (strong, strong_material, weak_material) =
+        match util::material_for_side(white) >= util::material_for_side(black) {
+            true => (Color::White, white, black),
+            false => (Color::Black, black, white),
+        };
+
+    if let Some(scale) = opposite_bishops(board, &white, &black) {
+        return scale;
+    }
+    if strong_material.pawn > 0 {
+        return match is_wrong_rook_pawn(board, strong, &strong_material, &weak_material) {
+            true => 0,
+            false => 100,
+        };
+    }
+
+    // no pawns to promote, the extra material has to be enough to mate
+    let bare_king = pieces(&weak_material) == 0 && weak_material.pawn == 0;
+    if strong_material.knight == 2 && pieces(&strong_material) == 2 && bare_king {
+        return 0; // two knights can't force mate
+    }
+    let advantage =
+        util::material_for_side(strong_material) - util::material_for_side(weak_material);
+    let single_minor =
+        pieces(&strong_material) == 1 && strong_material.rook == 0 && strong_material.queen == 0;
+    match advantage <= util::piece_value(Role::Bishop) || single_minor {
+        true => NO_PAWNS_SCALE,
+        false => 100,
+    }
+}
+
+/// queens or rooks, or two different minor pieces, mate a bare king
+fn can_force_mate(board: &Board, color: Color) -> bool {
+    let material = board.material_side(color);
+    let bishops = board.bishops() & board.by_color(color);
+    material.queen > 0
+        || material.rook > 0
+        || (material.bishop > 0 && material.knight > 0)
+        || ((bishops & Bitboard::DARK_SQUARES).any() && (bishops & !Bitboard::DARK_SQUARES).any())
+}
+
+fn is_bishop_and_knight(material: &ByRole<u8>) -> bool {
+    material.bishop == 1 && material.knight == 1 && pieces(material) == 2
+}
+
+/// pieces besides king and pawns
+fn pieces(material: &ByRole<u8>) -> u8 {
+    material.knight + material.bishop + material.rook + material.queen
+}
+
+/// king, bishop (or none) and pawns on a rook file against a bare king that reached the
+/// promotion corner. If the bishop can't cover the promotion square, the king can't be driven
+/// out and it's a draw
+fn is_wrong_rook_pawn(
+    board: &Board,
+    strong: Color,
+    strong_material: &ByRole<u8>,
+    weak_material: &ByRole<u8>,
+) -> bool {
+    let pawns = board.pawns() & board.by_color(strong);
+    let only_bishop =
+        strong_material.bishop == pieces(strong_material) && strong_material.bishop <= 1;
+    if !only_bishop || weak_material.pawn > 0 || pieces(weak_material) > 0 {
+        return false;
+    }
+    let file = if pawns.is_subset(Bitboard::from_file(File::A)) {
+        File::A
+    } else if pawns.is_subset(Bitboard::from_file(File::H)) {
+        File::H
+    } else {
+        return false;
+    };
+    let promotion_square = Square::from_coords(file, strong.fold_wb(Rank::Eighth, Rank::First));
+    let bishop_covers_promotion = (board.bishops() & board.by_color(strong))
+        .into_iter()
+        .any(|bishop| bishop.is_dark() == promotion_square.is_dark());
+    let defending_king = board.king_of(strong.other());
+    !bishop_covers_promotion
+        && defending_king.is_some_and(|king| king.distance(promotion_square) <= 1)
+}
+
+/// bishops of opposite colors and nothing else but pawns (or a rook each) are very drawish,
+/// each side can blockade the squares the other bishop doesn't reach
+fn opposite_bishops(board: &Board, white: &ByRole<u8>, black: &ByRole<u8>) -> Option<i32> {
+    let one_bishop_each = white.bishop == 1 && black.bishop == 1;
+    let bishops = board.bishops();
+    let opposite_colors = (bishops & Bitboard::DARK_SQUARES).count() == 1;
+    if !one_bishop_each || !opposite_colors {
+        return None;
+    }
+    let pawn_difference = white.pawn.abs_diff(black.pawn);
+    match (pieces(white), pieces(black), white.rook, black.rook) {
+        (1, 1, _, _) if pawn_difference <= 1 => Some(OPPOSITE_BISHOPS_SCALE),
+        (1, 1, _, _) => Some(OPPOSITE_BISHOPS_MANY_PAWNS_SCALE),
+        (2, 2, 1, 1) => Some(OPPOSITE_BISHOPS_ROOKS_SCALE),
+        _ => None,
+    }
+}
+
+/// 0 in the center up to 6 in the corners
+fn center_distance(square: Square) -> i32 {
+    let file = square.file() as i32;
+    let rank = square.rank() as i32;
+    (3 - file).max(file - 4) + (3 - rank).max(rank - 4)
+}
+
+fn manhattan_distance(a: Square, b: Square) -> i32 {
+    (a.file().distance(b.file()) + a.rank().distance(b.rank())) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use shakmaty::Position;
+
+    use super::*;
+
+    fn board(fen: &str) -> Board {
+        util::position(fen).board().clone()
+    }
+
+    #[test]
+    fn mop_up_drives_the_lone_king_to_the_edge() {
+        // the same material, with the lone king in the center and at the edge
+        for (center, edge, strong) in [
+            (
+                "8/8/8/3k4/8/8/8/Q3K3 w - - 0 1",
+                "3k4/8/8/8/8/8/8/Q3K3 w - - 0 1",
+                Color::White,
+            ),
+            (
+                "8/8/8/3k4/8/8/8/R3K3 w - - 0 1",
+                "3k4/8/8/8/8/8/8/R3K3 w - - 0 1",
+                Color::White,
+            ),
+            (
+                "8/8/8/3K4/8/8/8/r3k3 b - - 0 1",
+                "3K4/8/8/8/8/8/8/r3k3 b - - 0 1",
+                Color::Black,
+            ),
+        ] {
+            let (center, edge) = (board(center), board(edge));
+            assert!(mop_up(&center, strong) > 0);
+            assert!(mop_up(&edge, strong) > mop_up(&center, strong));
+            assert_eq!(mop_up(&center, strong.other()), -mop_up(&center, strong));
+        }
+        // not enough to mate
+        assert_eq!(
+            mop_up(&board("3k4/8/8/8/8/8/8/N3K3 w - - 0 1"), Color::White),
+            0
+        );
+    }
+
+    #[test]
+    fn bishop_and_knight_aim_for_the_bishops_corner() {
+        // light-squared bishop on d1, h1 and a8 are the mating corners
+        let right_corner = board("8/8/8/8/8/2N5/8/3BK2k w - - 0 1");
+        let wrong_corner = board("7k/8/8/8/8/2N5/8/3BK3 w - - 0 1");
+        assert!(mop_up(&right_corner, Color::White) > mop_up(&wrong_corner, Color::White));
+    }
+
+    #[test]
+    fn drawish_material_is_scaled_down() {
+        for (fen, expected) in [
+            // two knights can't force mate
+            ("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1", 0),
+            // the h-pawn promotes on a dark square, the light-squared bishop can't help
+            ("7k/8/8/8/8/8/7P/3BK3 w - - 0 1", 0),
+            ("7k/8/8/8/8/8/7P/2B1K3 w - - 0 1", 100),
+            // the lone king hasn't reached the corner yet
+            ("8/8/8/8/2k5/8/7P/3BK3 w - - 0 1", 100),
+            ("8/p7/8/8/8/8/8/4K2k w - - 0 1", 100),
+            ("8/p7/8/8/8/8/8/K6k w - - 0 1", 0),
+            // opposite-colored bishops, a dark one for white and a light one for black
+            ("2b1k3/5ppp/8/8/8/8/5PPP/2B1K3 w - - 0 1", 25),
+            ("2b1k3/7p/8/8/8/8/4PPPP/2B1K3 w - - 0 1", 50),
+            ("r1b1k3/5ppp/8/8/8/8/4PPPP/R1B1K3 w - - 0 1", 75),
+            // same-colored bishops are a normal endgame
+            ("5bk1/5ppp/8/8/8/8/4PPPP/2B1K3 w - - 0 1", 100),
+            // pawnless with only a minor piece more
+            ("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1", 25),
+            ("4k3/8/8/8/3b4/8/8/R3K3 w - - 0 1", 25),
+            ("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", 100),
+        ] {
+            assert_eq!(scale(&board(fen)), expected, "{fen}");
+        }
+    }
+}
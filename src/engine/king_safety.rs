@@ -0,0 +1,111 @@
+use shakmaty::{Bitboard, Board, Chess, Color, File, Position, Rank, Role, Square, attacks};
+
+use super::piece_square;
+
+/// per own pawn directly in front of the king (or one square diagonally in front)
+const SHIELD_PAWN_CLOSE: i32 = 12;
+/// per own pawn two squares in front of the king
+const SHIELD_PAWN_FAR: i32 = 6;
+/// per file on or next to the king without own pawns
+const SEMI_OPEN_FILE: i32 = -15;
+/// per file on or next to the king without any pawns
+const OPEN_FILE: i32 = -25;
+const CASTLED: i32 = 20;
+/// the king is still in the center and can't castle anymore
+const CASTLING_LOST: i32 = -25;
+/// how dangerous an enemy piece is per attacked square of the king zone, by role
+const ATTACK_WEIGHTS: [i32; 6] = [0, 20, 20, 40, 80, 0];
+/// percentage of the attack weight applied, by number of attacking pieces. A lone attacker is
+/// rarely dangerous, several together are
+const ATTACKER_SCALE: [i32; 8] = [0, 0, 50, 75, 88, 94, 97, 99];
+
+/// king safety from the perspective of the given side (own minus opponent's). Only matters
+/// while there are pieces left to attack the king, so it fades out with the game phase
+pub fn evaluate(position: &Chess, color: Color) -> i32 {
+    let middlegame = king_safety(position, color) - king_safety(position, color.other());
+    piece_square::taper(middlegame, 0, piece_square::game_phase(position.board()))
+}
+
+fn king_safety(position: &Chess, color: Color) -> i32 {
+    let board = position.board();
+    let Some(king) = board.king_of(color) else {
+        return 0;
+    };
+    pawn_shield(board, color, king.file(), king.rank())
+        + open_files(board, color, king.file())
+        + castling_status(position, color, king.file(), king.rank())
+        - king_zone_attacks(board, color, king)
+}
+
+fn pawn_shield(board: &Board, color: Color, king_file: File, king_rank: Rank) -> i32 {
+    let own_pawns = board.pawns() & board.by_color(color);
+    let forward = color.fold_wb(1, -1);
+    let close_rank = king_rank.offset(forward);
+    let far_rank = king_rank.offset(2 * forward);
+
+    let shield_files = files_around(king_file);
+    let count_on = |rank: Option<Rank>| {
+        rank.map_or(0, |rank| {
+            (own_pawns & shield_files & Bitboard::from_rank(rank)).count() as i32
+        })
+    };
+    count_on(close_rank) * SHIELD_PAWN_CLOSE + count_on(far_rank) * SHIELD_PAWN_FAR
+}
+
+fn open_files(board: &Board, color: Color, king_file: File) -> i32 {
+    let own_pawns = board.pawns() & board.by_color(color);
+    [king_file.offset(-1), Some(king_file), king_file.offset(1)]
+        .into_iter()
+        .flatten()
+        .map(Bitboard::from_file)
+        .map(|file| {
+            if (file & board.pawns()).is_empty() {
+                OPEN_FILE
+            } else if (file & own_pawns).is_empty() {
+                SEMI_OPEN_FILE
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+fn castling_status(position: &Chess, color: Color, king_file: File, king_rank: Rank) -> i32 {
+    if king_rank != color.fold_wb(Rank::First, Rank::Eighth) {
+        return 0; // the piece-square tables already punish walking the king up the board
+    }
+    if king_file <= File::C || king_file >= File::G {
+        CASTLED
+    } else if !position.castles().has_color(color) {
+        CASTLING_LOST
+    } else {
+        0
+    }
+}
+
+/// the king's surroundings attacked by enemy pieces, weighted by their role and by how many
+/// of them take part in the attack
+fn king_zone_attacks(board: &Board, color: Color, king: Square) -> i32 {
+    let king_zone = attacks::king_attacks(king).with(king);
+    let mut attackers = 0;
+    let mut weight = 0;
+    let enemies = board.by_color(color.other()) & !board.pawns() & !board.kings();
+    for square in enemies {
+        let attacked = board.attacks_from(square) & king_zone;
+        if attacked.any() {
+            let role = board.role_at(square).unwrap_or(Role::Pawn);
+            attackers += 1;
+            weight += ATTACK_WEIGHTS[role as usize - 1] * attacked.count() as i32;
+        }
+    }
+    weight * ATTACKER_SCALE[attackers.min(ATTACKER_SCALE.len() - 1)] / 100
+}
+
+fn files_around(file: File) -> Bitboard {
+    [file.offset(-1), Some(file), file.offset(1)]
+        .into_iter()
+        .flatten()
+        .fold(Bitboard::EMPTY, |files, file| {
+            files | Bitboard::from_file(file)
+        })
+}
@@ -1,81 +1,475 @@
 use std::{
-    cmp::min_by_key,
-    collections::HashMap,
-    ops::Add,
-    time::{SystemTime, UNIX_EPOCH},
+    cmp::Reverse,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
 };
 
 use crate::util;
 
-use super::Engine;
+use super::{
+    Engine, EngineConfig, GameClock, Score, SearchResult, StopSignal,
+    accumulator::Accumulator,
+    book::OpeningBook,
+    book_learning::LearningStore,
+    move_ordering::MoveOrderer,
+    nnue::{Network, NnueEvaluator},
+    score::{self, INFINITY, TABLEBASE_WIN},
+    see,
+    strategy::{Evaluator, StrategyScore},
+    tablebase::Tablebases,
+    thread_budget::{GameRegistration, ThreadReservation},
+    time_manager::TimeManager,
+    transposition::{self, Bound, NO_MOVE, TranspositionTable, TtEntry, pack_move},
+};
 use anyhow::Result;
 use async_trait::async_trait;
-use log::{debug, info};
-use shakmaty::{Chess, Color, Move, Position, Rank, uci::UciMove};
+use log::{debug, error, info, warn};
+use shakmaty::{
+    Chess, Color, EnPassantMode, KnownOutcome, Move, Position, Role, fen::Fen, uci::UciMove,
+};
+use shakmaty_syzygy::Wdl;
+use tokio::task::{JoinError, JoinHandle};
 
-const MAX_EVAL: i32 = 1_000_000;
-const MIN_EVAL: i32 = -1_000_000;
+const MAX_SEARCH_DEPTH: u8 = 64;
+/// hard limit for the distance to the root, including quiescence search
+const MAX_PLY: usize = 128;
+/// safety margin for delta pruning in quiescence search, covers positional compensation
+const DELTA_MARGIN: i32 = 200;
+const NULL_MOVE_MIN_DEPTH: u8 = 3;
+/// moves before this index in the ordered move list are never reduced
+const LMR_MIN_MOVE_INDEX: usize = 3;
+const LMR_MIN_DEPTH: u8 = 3;
+/// number of searched nodes between two checks of the clock
+const TIME_CHECK_INTERVAL: u64 = 1024;
+/// time a search gets after its hard time limit before it's stopped from the outside
+const STOP_GRACE_PERIOD: Duration = Duration::from_millis(100);
 
-pub enum Evaluation {
-    Additive(i32),
-    Absolute(i32),
-}
-impl Add for Evaluation {
-    type Output = Evaluation;
-
-    fn add(self, rhs: Self) -> Self::Output {
-        match self {
-            Evaluation::Additive(i) => match rhs {
-                Evaluation::Additive(j) => Evaluation::Additive(i + j),
-                Evaluation::Absolute(j) => Evaluation::Absolute(j),
-            },
-            Evaluation::Absolute(i) => match rhs {
-                Evaluation::Additive(_) => self,
-                // choose least extreme evaluation
-                Evaluation::Absolute(j) => Evaluation::Absolute(min_by_key(i, j, |num| num.abs())),
-            },
-        }
-    }
-}
-impl Evaluation {
-    pub fn to_i32(&self) -> i32 {
-        match *self {
-            Evaluation::Additive(v) => v,
-            Evaluation::Absolute(v) => v,
-        }
-    }
+/// loaded with the first game and shared by all games, the files can be large
+static NETWORK: OnceLock<Option<Arc<Network>>> = OnceLock::new();
+static BOOK: OnceLock<Option<Arc<OpeningBook>>> = OnceLock::new();
+
+/// without any pieces besides king and pawns, zugzwang is common and passing isn't an option
+fn has_non_pawn_material(game: &Chess, color: Color) -> bool {
+    let board = game.board();
+    let pieces = board.by_color(color) & !board.pawns() & !board.kings();
+    pieces.any()
 }
 
 struct StatsSubsystem {
-    current_target_eval: i32,
+    current_target_eval: Score,
     pruning_cutoffs: Vec<u32>,
+    nodes: u64,
+    quiescence_nodes: u64,
+    tt_probes: u64,
+    tt_hits: u64,
+    /// cutoffs caused by the first searched move, a measure of how good the move ordering is
+    first_move_cutoffs: u64,
+    null_move_tries: u64,
+    null_move_cutoffs: u64,
+    late_move_reductions: u64,
+    /// reduced searches that beat alpha and had to be repeated at full depth
+    late_move_re_searches: u64,
+    tablebase_hits: u64,
 }
 impl StatsSubsystem {
     fn new() -> Self {
         Self {
-            current_target_eval: 0,
+            current_target_eval: Score::Centipawns(0),
             pruning_cutoffs: Vec::new(),
+            nodes: 0,
+            quiescence_nodes: 0,
+            tt_probes: 0,
+            tt_hits: 0,
+            first_move_cutoffs: 0,
+            null_move_tries: 0,
+            null_move_cutoffs: 0,
+            late_move_reductions: 0,
+            late_move_re_searches: 0,
+            tablebase_hits: 0,
         }
     }
     fn reset_move_metrics(&mut self, search_depth: u8) {
         self.pruning_cutoffs = vec![0u32; search_depth as usize];
+        self.nodes = 0;
+        self.quiescence_nodes = 0;
+        self.tt_probes = 0;
+        self.tt_hits = 0;
+        self.first_move_cutoffs = 0;
+        self.null_move_tries = 0;
+        self.null_move_cutoffs = 0;
+        self.late_move_reductions = 0;
+        self.late_move_re_searches = 0;
+        self.tablebase_hits = 0;
+    }
+    fn record_cutoff(&mut self, depth: u8, move_index: usize) {
+        self.pruning_cutoffs[depth as usize - 1] += 1;
+        if move_index == 0 {
+            self.first_move_cutoffs += 1;
+        }
+    }
+    fn first_move_cutoff_rate(&self) -> f32 {
+        match self.pruning_cutoffs.iter().sum::<u32>() {
+            0 => 0.0,
+            cutoffs => self.first_move_cutoffs as f32 / cutoffs as f32 * 100.0,
+        }
+    }
+    fn tt_hit_rate(&self) -> f32 {
+        match self.tt_probes {
+            0 => 0.0,
+            probes => self.tt_hits as f32 / probes as f32 * 100.0,
+        }
     }
 }
 
 pub struct MainEngine {
     game: Chess,
+    /// hashes of all positions of the game so far, including the current one
+    position_history: Vec<u64>,
     color: Color,
-    stats: StatsSubsystem,
+    clock: Option<GameClock>,
+    config: EngineConfig,
+    /// shared by all searches of the game, so they can build on each other
+    tt: Arc<TranspositionTable>,
+    /// evaluates instead of the strategies if configured
+    network: Option<Arc<Network>>,
+    /// consulted before every search if configured
+    book: Option<Arc<OpeningBook>>,
+    /// results of earlier games with the book moves, if learning is enabled
+    learning: Option<LearningStore>,
+    /// position keys and book moves the bot played this game
+    book_moves: Vec<(u64, Move)>,
+    /// decide the moves of the endgames they cover if configured
+    tablebases: Option<Arc<Tablebases>>,
+    stop_signal: StopSignal,
+    /// moved to a blocking thread while a search is running
+    searcher: Option<Box<Searcher>>,
+    /// the opponent's reply expected by the last search, the move to ponder on
+    expected_reply: Option<Move>,
+    ponder_state: Arc<PonderState>,
+    /// search running on the opponent's time
+    ponder_search: Option<PonderSearch>,
+    /// the game's share of the cores depends on how many are played
+    _game: GameRegistration,
 }
 impl MainEngine {
-    pub fn new(initial_position: Chess, bot_color: Color) -> MainEngine {
-        MainEngine {
+    pub fn new(initial_position: Chess, bot_color: Color, config: EngineConfig) -> MainEngine {
+        let stop_signal = StopSignal::default();
+        let ponder_state = Arc::new(PonderState::default());
+        let tt = Arc::new(TranspositionTable::new(config.hash_size_mb));
+        let network = NETWORK
+            .get_or_init(|| {
+                let path = config.nnue_file.as_ref()?;
+                match Network::load(path) {
+                    Ok(network) => {
+                        info!("Evaluating with the network from {}", path.display());
+                        Some(Arc::new(network))
+                    }
+                    Err(e) => {
+                        warn!("{e:#}, falling back to the evaluation strategies");
+                        None
+                    }
+                }
+            })
+            .clone();
+        let book = BOOK
+            .get_or_init(|| {
+                let path = config.book_file.as_ref()?;
+                match OpeningBook::load(path) {
+                    Ok(book) => {
+                        info!(
+                            "Playing from the opening book {} ({} entries)",
+                            path.display(),
+                            book.entry_count()
+                        );
+                        Some(Arc::new(book))
+                    }
+                    Err(e) => {
+                        warn!("{e:#}, searching from the first move");
+                        None
+                    }
+                }
+            })
+            .clone();
+        let learning = match (&book, config.book_learning) {
+            (Some(_), true) => match LearningStore::load(&config.book_learning_file) {
+                Ok(learning) => Some(learning),
+                Err(e) => {
+                    warn!("{e:#}, playing the book without learning");
+                    None
+                }
+            },
+            _ => None,
+        };
+        let tablebases =
+            config
+                .syzygy_path
+                .as_ref()
+                .and_then(|path| match Tablebases::load(path) {
+                    Ok(tablebases) => {
+                        info!(
+                            "Probing tablebases with up to {} pieces from {}",
+                            tablebases.max_pieces(),
+                            path.display()
+                        );
+                        Some(Arc::new(tablebases))
+                    }
+                    Err(e) => {
+                        warn!("{e:#}, searching endgames without tablebases");
+                        None
+                    }
+                });
+        let mut engine = MainEngine {
+            position_history: vec![transposition::position_key(&initial_position)],
             game: initial_position,
             color: bot_color,
+            clock: None,
+            config,
+            tt,
+            network,
+            book,
+            learning,
+            book_moves: Vec::new(),
+            tablebases,
+            searcher: None,
+            stop_signal,
+            expected_reply: None,
+            ponder_state,
+            ponder_search: None,
+            _game: GameRegistration::new(),
+        };
+        engine.searcher = Some(engine.new_searcher());
+        engine
+    }
+
+    /// the searcher of the main thread, it creates its helpers itself
+    fn new_searcher(&self) -> Box<Searcher> {
+        Box::new(Searcher::new(
+            self.config.clone(),
+            Arc::clone(&self.tt),
+            self.network.clone(),
+            self.tablebases.clone(),
+            self.stop_signal.clone(),
+            Arc::clone(&self.ponder_state),
+            0,
+        ))
+    }
+
+    /// moves the searcher to a blocking thread, the search is CPU work which would otherwise
+    /// stall a worker thread of the async runtime
+    fn spawn_search(
+        &mut self,
+        position: Chess,
+        history: Vec<u64>,
+        timer: TimeManager,
+        pondering: bool,
+    ) -> Option<SearchTask> {
+        let Some(mut searcher) = self.searcher.take() else {
+            error!("Search requested while another one is still running");
+            return None;
+        };
+        // a ponder search holds its cores for the opponent's whole think time, the searches of
+        // other games need them more. A ponder hit restarts the search with all of them
+        let max_threads = match pondering {
+            true => 1,
+            false => self.config.threads,
+        };
+        let reservation = ThreadReservation::acquire(max_threads);
+        let threads = reservation.threads();
+
+        Some(tokio::task::spawn_blocking(move || {
+            let result = searcher.search(position, history, timer, threads, pondering);
+            drop(reservation);
+            (searcher, result)
+        }))
+    }
+
+    /// a move from the opening book instead of a search, if the position is in there
+    async fn book_move(&mut self) -> Option<SearchResult> {
+        let book = self.book.as_ref()?;
+        let book_move = book.pick(
+            &self.game,
+            self.config.book_temperature,
+            self.learning.as_ref(),
+        )?;
+        info!("Book move {book_move} from {}", book.path().display());
+        self.book_moves
+            .push((OpeningBook::key(&self.game), book_move.book_move));
+
+        // nothing to ponder on, the opponent's reply is likely in the book as well
+        self.discard_ponder_search().await;
+        Some(SearchResult {
+            best_move: book_move.book_move,
+            eval: Score::Centipawns(0),
+            principal_variation: vec![book_move.book_move],
+        })
+    }
+
+    /// the tablebase move instead of a search, if the position is covered by them
+    async fn tablebase_move(&mut self) -> Option<SearchResult> {
+        let (best_move, wdl, dtz) = self.tablebases.as_ref()?.best_move(&self.game)?;
+        info!(
+            "Tablebase move {best_move} ({wdl:?}, {} plies to zeroing)",
+            dtz.0.abs()
+        );
+
+        self.discard_ponder_search().await;
+        let eval = match wdl {
+            Wdl::Win => Score::TablebaseWin(0),
+            Wdl::Loss => Score::TablebaseLoss(0),
+            // the fifty-move rule turns cursed wins and blessed losses into draws
+            _ => Score::Draw,
+        };
+        Some(SearchResult {
+            best_move,
+            eval,
+            principal_variation: vec![best_move],
+        })
+    }
+
+    /// stops the search on the opponent's time if the move is decided without searching. Also
+    /// makes sure there's no pondering on the reply, as there's no expected one
+    async fn discard_ponder_search(&mut self) {
+        if let Some(ponder) = self.ponder_search.take() {
+            self.stop_signal.stop();
+            let search_task_result = ponder.task.await;
+            self.finish_search(search_task_result);
+        }
+        self.expected_reply = None;
+    }
+
+    /// takes the searcher back from a finished search
+    fn finish_search(&mut self, search_task_result: SearchTaskResult) -> Option<SearchResult> {
+        match search_task_result {
+            Ok((searcher, result)) => {
+                self.searcher = Some(searcher);
+                result
+            }
+            Err(e) => {
+                // the searcher went down with the thread, the next search needs a new one
+                error!("Search thread failed: {e}");
+                self.searcher = Some(self.new_searcher());
+                None
+            }
+        }
+    }
+}
+impl Drop for MainEngine {
+    fn drop(&mut self) {
+        // a ponder search has no time limit, it would keep running after the game is over
+        self.stop_signal.stop();
+    }
+}
+
+type SearchTask = JoinHandle<(Box<Searcher>, Option<SearchResult>)>;
+type SearchTaskResult = Result<(Box<Searcher>, Option<SearchResult>), JoinError>;
+
+struct PonderSearch {
+    expected_move: Move,
+    task: SearchTask,
+}
+
+/// lets a running ponder search know when the opponent played the expected move
+#[derive(Default)]
+struct PonderState {
+    active: AtomicBool,
+    /// clock at the time of the ponder hit, the search gets a regular time budget from it
+    clock: Mutex<Option<GameClock>>,
+}
+impl PonderState {
+    fn start(&self) {
+        self.active.store(true, Ordering::Release);
+    }
+
+    fn hit(&self, clock: Option<GameClock>) {
+        *self.clock.lock().unwrap() = clock;
+        self.active.store(false, Ordering::Release);
+    }
+
+    fn is_active(&self) -> bool {
+        self.active.load(Ordering::Acquire)
+    }
+
+    fn clock(&self) -> Option<GameClock> {
+        *self.clock.lock().unwrap()
+    }
+}
+
+/// owns everything a search needs, so the whole search can be moved off the async runtime
+struct Searcher {
+    /// root position of the current search
+    game: Chess,
+    config: EngineConfig,
+    /// 0 for the main thread, which decides when the search is over, helpers count up from 1
+    thread_id: usize,
+    timer: TimeManager,
+    stop_signal: StopSignal,
+    search_aborted: bool,
+    /// hashes of the game's positions followed by the current search path, to detect repetitions
+    key_history: Vec<u64>,
+    /// index of the root position in key_history
+    root_index: usize,
+    ponder_state: Arc<PonderState>,
+    /// searching on the opponent's time, without a time limit until the ponder hit
+    pondering: bool,
+    tt: Arc<TranspositionTable>,
+    evaluator: Evaluator,
+    /// replaces the evaluator if a network is configured
+    nnue: Option<NnueEvaluator>,
+    /// exact scores for the endgames they cover
+    tablebases: Option<Arc<Tablebases>>,
+    /// material and piece-square sums of every position on the current search path, the last
+    /// one belongs to the position being searched
+    accumulators: Vec<Accumulator>,
+    move_orderer: MoveOrderer,
+    stats: StatsSubsystem,
+    /// searchers for the helper threads of lazy SMP, only used by the main thread
+    helpers: Vec<Searcher>,
+}
+impl Searcher {
+    fn new(
+        config: EngineConfig,
+        tt: Arc<TranspositionTable>,
+        network: Option<Arc<Network>>,
+        tablebases: Option<Arc<Tablebases>>,
+        stop_signal: StopSignal,
+        ponder_state: Arc<PonderState>,
+        thread_id: usize,
+    ) -> Self {
+        let evaluator = Evaluator::new(&config.strategies);
+        Self {
+            game: Chess::default(),
+            config,
+            thread_id,
+            timer: TimeManager::new(None, Color::White),
+            stop_signal,
+            search_aborted: false,
+            key_history: Vec::new(),
+            root_index: 0,
+            ponder_state,
+            pondering: false,
+            tt,
+            evaluator,
+            nnue: network.map(NnueEvaluator::new),
+            tablebases,
+            accumulators: Vec::with_capacity(MAX_PLY + 1),
+            move_orderer: MoveOrderer::new(MAX_SEARCH_DEPTH as usize),
             stats: StatsSubsystem::new(),
+            helpers: Vec::new(),
         }
     }
 }
+
+/// what the iterative deepening loop of a single thread came up with
+struct IterationResult {
+    completed_depth: u8,
+    /// root moves with their evaluation, best first
+    evaluated_moves: Vec<(Move, i32)>,
+    principal_variation: Vec<Move>,
+}
 #[async_trait]
 impl Engine for MainEngine {
     fn is_my_turn(&self) -> bool {
@@ -88,192 +482,870 @@ impl Engine for MainEngine {
 
     async fn update_board(&mut self, move_played: UciMove) -> Result<()> {
         let valid_move = move_played.to_move(&self.game)?;
+
+        if let Some(ponder) = self.ponder_search.take() {
+            if ponder.expected_move == valid_move {
+                info!("Ponder hit ({valid_move}), continuing the search");
+                self.ponder_search = Some(ponder);
+            } else {
+                info!(
+                    "Ponder miss (expected {}, got {valid_move}), discarding the search",
+                    ponder.expected_move
+                );
+                self.stop_signal.stop();
+                let search_task_result = ponder.task.await;
+                self.finish_search(search_task_result);
+            }
+        }
+
         self.game.play_unchecked(valid_move);
+        self.position_history
+            .push(transposition::position_key(&self.game));
         Ok(())
     }
 
-    async fn search(&mut self) -> Option<Move> {
-        let start_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    fn update_clock(&mut self, clock: GameClock) {
+        self.clock = Some(clock);
+    }
 
-        let mut legal_moves = self
-            .game
-            .legal_moves()
-            .into_iter()
-            .map(|m| (m, 0))
-            .collect::<HashMap<_, _>>();
+    fn stop_signal(&self) -> StopSignal {
+        self.stop_signal.clone()
+    }
 
-        if legal_moves.is_empty() || self.game.is_game_over() {
-            return None;
+    fn explain(&self, position: &Chess) -> Vec<StrategyScore> {
+        if let Some(network) = &self.network {
+            // the network is all there is to the evaluation
+            let mut nnue = NnueEvaluator::new(Arc::clone(network));
+            nnue.reset(position.board());
+            let score = nnue.evaluate(position);
+            return vec![StrategyScore {
+                name: "nnue",
+                raw: score,
+                weight: 100,
+                weighted: score,
+            }];
         }
+        // the searcher's evaluator may be busy in a search thread, a fresh one does the job
+        Evaluator::new(&self.config.strategies).explain(position)
+    }
 
-        info!(
-            "Searching for response. {} possible legal moves available",
-            legal_moves.len()
-        );
+    fn game_over(&mut self, outcome: KnownOutcome) {
+        self.stop_signal.stop(); // in case it's pondering
+        let Some(learning) = &mut self.learning else {
+            return;
+        };
+        if self.book_moves.is_empty() {
+            return;
+        }
+        // only once per game, whatever the caller does
+        let book_moves = std::mem::take(&mut self.book_moves);
+        match learning.record(&book_moves, outcome, self.color) {
+            Ok(()) => info!(
+                "Recorded the result ({outcome}) for {} book moves in {}",
+                book_moves.len(),
+                learning.path().display()
+            ),
+            Err(e) => warn!("{e:#}, the result of this game isn't learned"),
+        }
+    }
+
+    fn start_pondering(&mut self) {
+        let Some(expected_reply) = self.expected_reply.take() else {
+            return;
+        };
+        if !self.config.ponder
+            || self.ponder_search.is_some()
+            || !self.game.is_legal(expected_reply)
+        {
+            return;
+        }
+
+        let mut position = self.game.clone();
+        position.play_unchecked(expected_reply);
+        if position.is_game_over() {
+            return;
+        }
+
+        info!("Pondering on {expected_reply}");
+        self.stop_signal.reset();
+        self.ponder_state.start();
+        let mut history = self.position_history.clone();
+        history.push(transposition::position_key(&position));
+        if let Some(task) = self.spawn_search(position, history, TimeManager::infinite(), true) {
+            self.ponder_search = Some(PonderSearch {
+                expected_move: expected_reply,
+                task,
+            });
+        }
+    }
+
+    fn prepare_search(&mut self) {
+        self.stop_signal.reset();
+    }
+
+    async fn search(&mut self) -> Option<SearchResult> {
+        if let Some(result) = self.book_move().await {
+            return Some(result);
+        }
+        if let Some(result) = self.tablebase_move().await {
+            return Some(result);
+        }
+
+        let timer = TimeManager::new(self.clock, self.game.turn());
+        let deadline = timer.maximum() + STOP_GRACE_PERIOD;
 
-        // TODO: dynamically adjust search_depth based on time_remaining?
-        // TODO: variable depth based on early,mid,end-game or strictly by material/piece count
-        let search_depth = 4;
-        self.stats.reset_move_metrics(search_depth);
-
-        // pass updated alpha (best eval bot can force against sensible enemy, which was seen before) to next children
-        let mut alpha = MIN_EVAL - 1000; // make sure it's still smaller than checkmate
-        legal_moves.iter_mut().for_each(|(legal_move, eval)| {
-            *eval = self.deep_move_evaluation(
-                self.game.clone(),
-                legal_move,
-                search_depth - 1,
-                alpha,
-                MAX_EVAL,
+        let mut search_task = match self.ponder_search.take() {
+            // the opponent played the expected move, the ponder search now gets a time budget
+            Some(ponder) if self.config.threads <= 1 => {
+                self.ponder_state.hit(self.clock);
+                ponder.task
+            }
+            ponder => {
+                if let Some(ponder) = ponder {
+                    // the ponder search only had one thread. The new search gets its share of
+                    // them and finds the work already done in the transposition table
+                    self.stop_signal.stop();
+                    let search_task_result = ponder.task.await;
+                    self.finish_search(search_task_result);
+                    self.stop_signal.reset();
+                }
+                let history = self.position_history.clone();
+                self.spawn_search(self.game.clone(), history, timer, false)?
+            }
+        };
+
+        // the search watches the clock itself, this is only the safety net
+        let search_task_result = tokio::select! {
+            result = &mut search_task => result,
+            _ = tokio::time::sleep(deadline) => {
+                warn!("Search exceeded its time budget, stopping it");
+                self.stop_signal.stop();
+                search_task.await
+            }
+        };
+
+        let result = self.finish_search(search_task_result);
+        self.expected_reply = result
+            .as_ref()
+            .and_then(|result| result.principal_variation.get(1).copied());
+        result
+    }
+}
+
+impl Searcher {
+    /// searches the position with the given number of threads. Lazy SMP: helper threads search
+    /// the same position and only contribute through the shared transposition table. Their
+    /// slightly different timing makes them explore different parts of the tree, which fills the
+    /// table with results the main thread can reuse.
+    fn search(
+        &mut self,
+        position: Chess,
+        history: Vec<u64>,
+        timer: TimeManager,
+        threads: usize,
+        pondering: bool,
+    ) -> Option<SearchResult> {
+        if position.legal_moves().is_empty() || position.is_game_over() {
+            return None;
+        }
+
+        self.tt.new_search();
+        let helper_stop_signal = StopSignal::default();
+        while self.helpers.len() < threads - 1 {
+            let thread_id = self.helpers.len() + 1;
+            let helper = Searcher::new(
+                self.config.clone(),
+                Arc::clone(&self.tt),
+                self.nnue.as_ref().map(NnueEvaluator::network),
+                self.tablebases.clone(),
+                helper_stop_signal.clone(),
+                Arc::clone(&self.ponder_state),
+                thread_id,
             );
-            alpha = alpha.max(*eval);
+            self.helpers.push(helper);
+        }
+
+        // helpers never look at the clock while pondering, the main thread stops them
+        self.pondering = pondering;
+        let mut helpers = std::mem::take(&mut self.helpers);
+        let result = thread::scope(|scope| {
+            for helper in helpers.iter_mut().take(threads - 1) {
+                helper.stop_signal = helper_stop_signal.clone();
+                let position = position.clone();
+                let history = &history;
+                scope.spawn(move || helper.iterative_deepening(position, history, timer));
+            }
+
+            let result = self.iterative_deepening(position, &history, timer);
+            helper_stop_signal.stop();
+            result
         });
+        let helper_nodes = helpers
+            .iter()
+            .take(threads - 1)
+            .map(|helper| helper.stats.nodes + helper.stats.quiescence_nodes)
+            .sum::<u64>();
+        self.helpers = helpers;
 
-        // sort and get best move
-        let mut evaluated_moves = legal_moves.into_iter().collect::<Vec<(_, _)>>();
-        evaluated_moves.sort_by_key(|(_, eval)| *eval);
-        evaluated_moves.reverse();
+        let IterationResult {
+            completed_depth,
+            evaluated_moves,
+            mut principal_variation,
+        } = result;
         let (chosen_move, best_eval) = *evaluated_moves.first().unwrap();
+        let best_score = Score::from_search_value(best_eval);
+        if principal_variation.first() != Some(&chosen_move) {
+            principal_variation = vec![chosen_move];
+        }
 
         // log stats and debug info
-        // TODO: improve stats subsystem to show actual lines to make debugging easier
-        let execution_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap() - start_time;
         info!(
-            "Chose {chosen_move} (eval: {} -> {best_eval}, searched: {:.2}s, alpha-beta cutoffs: | {} | )",
+            "Chose {chosen_move} (eval: {} -> {best_score}, depth: {completed_depth}, threads: {threads}, nodes: {} (+{} quiescence, +{helper_nodes} helpers), tt hits: {:.1}%, first-move cutoffs: {:.1}%, null-move cutoffs: {}/{}, late-move re-searches: {}/{}, tablebase hits: {}, searched: {:.2}s, pawn table hits: {:.1}%, alpha-beta cutoffs: | {} | )",
             self.stats.current_target_eval,
-            execution_time.as_secs_f32(),
+            self.stats.nodes,
+            self.stats.quiescence_nodes,
+            self.stats.tt_hit_rate(),
+            self.stats.first_move_cutoff_rate(),
+            self.stats.null_move_cutoffs,
+            self.stats.null_move_tries,
+            self.stats.late_move_re_searches,
+            self.stats.late_move_reductions,
+            self.stats.tablebase_hits,
+            self.timer.elapsed().as_secs_f32(),
+            self.evaluator.pawn_table_hit_rate(),
             self.stats
                 .pruning_cutoffs
                 .iter()
+                .take(completed_depth as usize)
                 .rev()
                 .enumerate()
                 .map(|(i, n)| { format!("{i} : {n}") })
                 .collect::<Vec<_>>()
                 .join(" | ")
         );
+        info!(
+            "Expected line: {}",
+            util::format_line(&self.game, &principal_variation)
+        );
         debug!(
             "Calculated lines were: \n{}",
             evaluated_moves
                 .into_iter()
-                .map(|(m, e)| format!("{:>6}  :  {:+}", m.to_string(), e))
+                .map(|(m, e)| format!("{:>6}  :  {}", m.to_string(), Score::from_search_value(e)))
                 .collect::<Vec<_>>()
                 .join("\n")
         );
+        debug!("Strategies: {}", self.evaluator.stats_summary());
+        if log::log_enabled!(log::Level::Debug) {
+            // the evaluation the search settled on comes from the end of the expected line
+            let mut line_end = self.game.clone();
+            for m in &principal_variation {
+                line_end.play_unchecked(*m);
+            }
+            debug!(
+                "Evaluation at the end of the expected line ({} to move): \n{}",
+                line_end.turn(),
+                self.evaluator
+                    .explain(&line_end)
+                    .iter()
+                    .map(|score| score.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
 
-        self.stats.current_target_eval = best_eval;
+        self.stats.current_target_eval = best_score;
 
-        Some(chosen_move)
+        Some(SearchResult {
+            best_move: chosen_move,
+            eval: best_score,
+            principal_variation,
+        })
     }
-}
 
-impl MainEngine {
-    /// adaptation of the minimax algorithm with alpha-beta pruning
-    fn deep_move_evaluation(
+    /// iterative deepening: search depth 1, 2, 3, ... until the time budget is used up or the
+    /// search gets stopped. Only fully completed iterations are trusted, an aborted one falls back
+    /// to the previous.
+    fn iterative_deepening(
         &mut self,
-        mut game_state: Chess,
-        legal_move: &Move,
+        position: Chess,
+        history: &[u64],
+        timer: TimeManager,
+    ) -> IterationResult {
+        self.game = position;
+        self.timer = timer;
+        self.search_aborted = false;
+        self.key_history.clear();
+        self.key_history.extend_from_slice(history);
+        self.root_index = history.len() - 1;
+        self.accumulators.clear();
+        self.accumulators.push(Accumulator::new(self.game.board()));
+        if let Some(nnue) = &mut self.nnue {
+            nnue.reset(self.game.board());
+        }
+        self.move_orderer.new_search();
+        self.stats.reset_move_metrics(MAX_SEARCH_DEPTH);
+        self.evaluator.reset_stats();
+        let is_main_thread = self.thread_id == 0;
+
+        // later iterations keep the root moves sorted by the previous iteration's results
+        let mut root_moves = self.game.legal_moves();
+        let hash_move = self
+            .tt
+            .probe(transposition::position_key(&self.game))
+            .map_or(NO_MOVE, |entry| entry.best_move);
+        self.move_orderer
+            .order_moves(&self.game, &mut root_moves, hash_move, 0);
+        let mut legal_moves = root_moves.into_iter().map(|m| (m, 0)).collect::<Vec<_>>();
+
+        // a ponder search has no budget yet, start_pondering already logged it
+        if is_main_thread && !self.pondering {
+            info!(
+                "Searching for response. {} possible legal moves available (budget: {:.2}s, max: {:.2}s)",
+                legal_moves.len(),
+                self.timer.optimum().as_secs_f32(),
+                self.timer.maximum().as_secs_f32()
+            );
+        }
+
+        // every other helper skips the first depth, so the threads don't search in lockstep
+        let first_depth = 1 + (self.thread_id % 2) as u8;
+        let mut completed_depth = 0;
+        let mut evaluated_moves = Vec::new();
+        let mut principal_variation = Vec::new();
+        for search_depth in first_depth..=MAX_SEARCH_DEPTH {
+            self.check_ponder_hit();
+            // helpers keep searching until the main thread is done
+            if is_main_thread && search_depth > 1 && !self.timer.should_start_iteration() {
+                break;
+            }
+
+            let pv = self.search_root(&mut legal_moves, search_depth);
+            if self.search_aborted {
+                debug!(
+                    "Aborted search at depth {search_depth} (thread {})",
+                    self.thread_id
+                );
+                break;
+            }
+
+            // best moves first, so the next iteration searches them first (stable sort keeps the
+            // previous order for equal evaluations)
+            legal_moves.sort_by_key(|(_, eval)| Reverse(*eval));
+            evaluated_moves = legal_moves.clone();
+            principal_variation = pv;
+            completed_depth = search_depth;
+
+            let (best_move, best_eval) = legal_moves[0];
+            if is_main_thread {
+                debug!(
+                    "Depth {search_depth} completed: {best_move} ({}) after {:.2}s, line: {}",
+                    Score::from_search_value(best_eval),
+                    self.timer.elapsed().as_secs_f32(),
+                    util::format_line(&self.game, &principal_variation)
+                );
+            }
+
+            // searching deeper won't find a faster forced mate
+            if score::is_mate_value(best_eval) {
+                break;
+            }
+        }
+
+        if evaluated_moves.is_empty() {
+            // not even depth 1 finished, better play any legal move than none
+            evaluated_moves = legal_moves;
+        }
+
+        IterationResult {
+            completed_depth,
+            evaluated_moves,
+            principal_variation,
+        }
+    }
+
+    /// searches every root move to the given depth and stores its evaluation next to it.
+    /// Returns the principal variation, the line both sides are expected to play.
+    fn search_root(&mut self, root_moves: &mut [(Move, i32)], depth: u8) -> Vec<Move> {
+        let mut alpha = -INFINITY;
+        let beta = INFINITY;
+        let mut pv = Vec::new();
+        let mut child_pv = Vec::new();
+
+        for (i, (root_move, eval)) in root_moves.iter_mut().enumerate() {
+            let mut child = self.game.clone();
+            child.play_unchecked(*root_move);
+            self.make_move(self.game.turn(), root_move, &child);
+
+            *eval = if i == 0 {
+                -self.negamax(&child, depth - 1, 1, -beta, -alpha, true, &mut child_pv)
+            } else {
+                // the first move is most likely the best, try to prove the others are worse with
+                // a cheap null window search. Only if that fails, search them with the full window
+                let mut eval = -self.negamax(
+                    &child,
+                    depth - 1,
+                    1,
+                    -alpha - 1,
+                    -alpha,
+                    true,
+                    &mut child_pv,
+                );
+                if eval > alpha && eval < beta {
+                    eval = -self.negamax(&child, depth - 1, 1, -beta, -alpha, true, &mut child_pv);
+                }
+                eval
+            };
+            self.unmake_move();
+            if self.search_aborted {
+                break;
+            }
+
+            if *eval > alpha {
+                alpha = *eval;
+                pv.clear();
+                pv.push(*root_move);
+                pv.extend_from_slice(&child_pv);
+            }
+        }
+
+        pv
+    }
+
+    /// negamax alpha-beta search with principal variation search (fail-soft). Evaluations are
+    /// always from the perspective of the side to move, so the score of a move is the negated
+    /// score of the position it leads to. The best line found is written into `pv`.
+    #[allow(clippy::too_many_arguments)]
+    fn negamax(
+        &mut self,
+        game_state: &Chess,
         depth: u8,
-        mut alpha: i32, // highest eval the bot can force, assuming best play from opponent
-        mut beta: i32,  // smallest eval the opponent can force, assuming best play from bot
+        ply: usize,     // distance to the root
+        mut alpha: i32, // the side to move can already force at least this
+        beta: i32,      // the opponent can already force at most this, no need to look further
+        allow_null_move: bool,
+        pv: &mut Vec<Move>,
     ) -> i32 {
-        game_state.play_unchecked(*legal_move);
+        pv.clear();
+        self.stats.nodes += 1;
+        if self.check_search_aborted() {
+            return 0; // result gets discarded anyway
+        }
 
-        if depth == 0 || game_state.is_game_over() {
-            return self.evaluate_position(&game_state);
+        if game_state.is_game_over() {
+            return self.evaluate_position(game_state, ply);
+        }
+        let key = transposition::position_key(game_state);
+        if game_state.halfmoves() >= 100 || self.is_repetition(key, game_state.halfmoves()) {
+            return self.draw_score(game_state);
+        }
+        if depth == 0 {
+            return self.quiescence(game_state, ply, 0, alpha, beta);
         }
 
-        let legal_moves = game_state.legal_moves();
+        // a previous search of this position (from another move order or iteration) might already
+        // be good enough, otherwise at least its best move is the most promising one to try first.
+        // Nodes on the principal variation are always searched, so we get the full line.
+        let is_pv_node = beta - alpha > 1;
+        let mut hash_move = NO_MOVE;
+        self.stats.tt_probes += 1;
+        if let Some(entry) = self.tt.probe(key) {
+            self.stats.tt_hits += 1;
+            hash_move = entry.best_move;
+            let tt_eval = score::from_tt_value(entry.score, ply);
+            if !is_pv_node && entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return tt_eval,
+                    Bound::Lower if tt_eval >= beta => return tt_eval,
+                    Bound::Upper if tt_eval <= alpha => return tt_eval,
+                    _ => {}
+                }
+            }
+        }
+
+        // the tablebases know the outcome, no need to search any further. Probed right after
+        // captures and pawn moves, which is when the number of pieces drops into their range
+        if let Some(wdl) = self
+            .tablebases
+            .as_ref()
+            .and_then(|tablebases| tablebases.probe_wdl(game_state))
+        {
+            self.stats.tablebase_hits += 1;
+            return match wdl {
+                Wdl::Win => TABLEBASE_WIN - ply as i32,
+                Wdl::Loss => -TABLEBASE_WIN + ply as i32,
+                // the fifty-move rule turns cursed wins and blessed losses into draws
+                _ => self.draw_score(game_state),
+            };
+        }
+
+        let turn = game_state.turn();
+        let in_check = game_state.is_check();
+        // popped again at the end, an aborted search resets the whole path anyway
+        self.key_history.push(key);
+
+        // null-move pruning: give the opponent a free move. If a reduced search still fails high,
+        // our position is so good that a real move will almost certainly fail high as well.
+        // Not sound in zugzwang, so skipped when in check or with only king and pawns left.
+        if self.config.null_move_pruning
+            && allow_null_move
+            && !is_pv_node
+            && !in_check
+            && depth >= NULL_MOVE_MIN_DEPTH
+            && has_non_pawn_material(game_state, turn)
+            && self.evaluate_position(game_state, ply) >= beta
+            && let Ok(null_move_state) = game_state.clone().swap_turn()
+        {
+            self.stats.null_move_tries += 1;
+            let reduction = if depth >= 6 { 3 } else { 2 };
+            let null_eval = -self.negamax(
+                &null_move_state,
+                depth - 1 - reduction,
+                ply + 1,
+                -beta,
+                -beta + 1,
+                false,
+                &mut Vec::new(),
+            );
+            if self.search_aborted {
+                return 0;
+            }
+            if null_eval >= beta {
+                self.stats.null_move_cutoffs += 1;
+                self.key_history.pop();
+                // a mate found after passing isn't a real mate
+                return if score::is_mate_value(null_eval) {
+                    beta
+                } else {
+                    null_eval
+                };
+            }
+        }
+
+        let mut legal_moves = game_state.legal_moves();
         assert!(!legal_moves.is_empty()); // terminated games should have been catched earlier
 
-        // TODO: sort moves by likelyhood of being good to get the most out of pruning
+        // sort moves by likelyhood of being good to get the most out of pruning
+        self.move_orderer
+            .order_moves(game_state, &mut legal_moves, hash_move, ply);
 
-        let is_bots_turn = game_state.turn() == self.color;
-        let mut deeper_eval = if is_bots_turn { MIN_EVAL } else { MAX_EVAL };
+        let alpha_orig = alpha;
+        let mut best_eval = -INFINITY;
+        let mut best_move = NO_MOVE;
+        let mut child_pv = Vec::new();
 
-        for m in legal_moves {
-            let eval = self.deep_move_evaluation(game_state.clone(), &m, depth - 1, alpha, beta);
-            if is_bots_turn {
-                deeper_eval = deeper_eval.max(eval); // maximize bots evaluation on his turn
-                alpha = alpha.max(eval);
-                // TODO: > vs >= (bot only plays well with > and <, I don't fully understand why,
-                // conceptually pruning already makes sense for >=) -> investigate further
-                if eval > beta {
-                    self.stats.pruning_cutoffs[depth as usize - 1] += 1;
+        for (i, m) in legal_moves.iter().enumerate() {
+            let mut child = game_state.clone();
+            child.play_unchecked(*m);
+            self.make_move(turn, m, &child);
+
+            let eval = if i == 0 {
+                -self.negamax(
+                    &child,
+                    depth - 1,
+                    ply + 1,
+                    -beta,
+                    -alpha,
+                    true,
+                    &mut child_pv,
+                )
+            } else {
+                // late move reductions: thanks to move ordering, late quiet moves are rarely good.
+                // Search them shallower first and only re-search at full depth if they beat alpha.
+                let reduction = if self.config.late_move_reductions
+                    && i >= LMR_MIN_MOVE_INDEX
+                    && depth >= LMR_MIN_DEPTH
+                    && !in_check
+                    && !m.is_capture()
+                    && !m.is_promotion()
+                    && !child.is_check()
+                {
+                    if i >= 2 * LMR_MIN_MOVE_INDEX && depth >= 2 * LMR_MIN_DEPTH {
+                        2
+                    } else {
+                        1
+                    }
+                } else {
+                    0
+                };
+
+                let mut eval = -self.negamax(
+                    &child,
+                    depth - 1 - reduction,
+                    ply + 1,
+                    -alpha - 1,
+                    -alpha,
+                    true,
+                    &mut child_pv,
+                );
+                if reduction > 0 {
+                    self.stats.late_move_reductions += 1;
+                    if eval > alpha {
+                        self.stats.late_move_re_searches += 1;
+                        eval = -self.negamax(
+                            &child,
+                            depth - 1,
+                            ply + 1,
+                            -alpha - 1,
+                            -alpha,
+                            true,
+                            &mut child_pv,
+                        );
+                    }
+                }
+                if eval > alpha && eval < beta {
+                    eval = -self.negamax(
+                        &child,
+                        depth - 1,
+                        ply + 1,
+                        -beta,
+                        -alpha,
+                        true,
+                        &mut child_pv,
+                    );
+                }
+                eval
+            };
+            self.unmake_move();
+            if self.search_aborted {
+                return 0; // don't pollute the table with unfinished results
+            }
+
+            if eval > best_eval {
+                best_eval = eval;
+                best_move = pack_move(m);
+                if eval > alpha {
+                    alpha = eval;
+                    pv.clear();
+                    pv.push(*m);
+                    pv.extend_from_slice(&child_pv);
+                }
+                if eval >= beta {
+                    self.stats.record_cutoff(depth, i);
+                    self.move_orderer.record_cutoff(m, depth, ply, turn);
                     break;
                 }
-            } else {
-                deeper_eval = deeper_eval.min(eval); // assume opponent wants to win too
-                beta = beta.min(eval);
-                if eval < alpha {
-                    self.stats.pruning_cutoffs[depth as usize - 1] += 1;
+            }
+        }
+
+        self.key_history.pop();
+
+        // scores outside the window only tell us on which side of it the true value lies
+        let bound = if best_eval >= beta {
+            Bound::Lower
+        } else if best_eval <= alpha_orig {
+            Bound::Upper
+        } else {
+            Bound::Exact
+        };
+        self.tt.store(
+            key,
+            TtEntry {
+                depth,
+                bound,
+                score: score::to_tt_value(best_eval, ply),
+                best_move,
+            },
+        );
+
+        best_eval
+    }
+
+    /// keeps resolving captures and promotions (and optionally checks) at the end of the main
+    /// search, so a line is never evaluated right before the queen gets taken one ply later
+    fn quiescence(
+        &mut self,
+        game_state: &Chess,
+        ply: usize,
+        quiescence_ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.stats.quiescence_nodes += 1;
+        if self.check_search_aborted() {
+            return 0;
+        }
+
+        let stand_pat = self.evaluate_position(game_state, ply);
+        if game_state.is_game_over() || ply >= MAX_PLY {
+            return stand_pat;
+        }
+
+        let in_check = game_state.is_check();
+
+        // stand pat: the side to move isn't forced to capture, it can (usually) keep the static
+        // evaluation with a quiet move instead. Not an option when in check though.
+        let mut best_eval = stand_pat;
+        if in_check {
+            best_eval = -INFINITY;
+        } else {
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+        }
+
+        let mut moves = if in_check {
+            game_state.legal_moves() // all evasions
+        } else {
+            let mut moves = game_state.capture_moves();
+            moves.extend(
+                game_state
+                    .promotion_moves()
+                    .into_iter()
+                    .filter(|m| !m.is_capture()),
+            );
+            if self.config.quiescence_checks && quiescence_ply == 0 {
+                let quiet_checks = game_state
+                    .legal_moves()
+                    .into_iter()
+                    .filter(|m| !m.is_capture() && !m.is_promotion())
+                    .filter(|m| game_state.clone().play(*m).is_ok_and(|p| p.is_check()))
+                    .collect::<Vec<_>>();
+                moves.extend(quiet_checks);
+            }
+            moves
+        };
+        self.move_orderer
+            .order_moves(game_state, &mut moves, NO_MOVE, ply);
+
+        for m in moves {
+            // delta pruning: even winning the captured piece for free can't raise alpha
+            if !in_check {
+                let gain = m.capture().map_or(0, util::piece_value)
+                    + m.promotion().map_or(0, |role| {
+                        util::piece_value(role) - util::piece_value(Role::Pawn)
+                    });
+                if stand_pat + gain + DELTA_MARGIN < alpha {
+                    continue;
+                }
+                // losing captures won't change the outcome, the side to move just wouldn't play them
+                if (m.is_capture() || m.is_promotion()) && see::see(game_state, &m) < 0 {
+                    continue;
+                }
+            }
+
+            let mut child = game_state.clone();
+            child.play_unchecked(m);
+            self.make_move(game_state.turn(), &m, &child);
+            let eval = -self.quiescence(&child, ply + 1, quiescence_ply + 1, -beta, -alpha);
+            self.unmake_move();
+
+            if eval > best_eval {
+                best_eval = eval;
+                alpha = alpha.max(eval);
+                if eval >= beta {
                     break;
                 }
             }
         }
 
-        deeper_eval
+        best_eval
     }
 
-    /// the actual evaluation function, which combines the expected positional value of each
-    /// applied strategy/tactic-function (Sum operator of Evaluation is adjusted)
-    /// Most strategy functions should only nudge the Evaluation a tiny bit compared to the
-    /// material_difference strategy(Pawn-win = +100), so they apply only in case of not having
-    /// the oportunity to win material directly. Exceptions: Checkmate and Stalemate strategies.
-    fn evaluate_position(&mut self, game_state: &Chess) -> i32 {
-        // TODO: need performance metrics per strategy and overall
-        let strategies: Vec<fn(&Chess, Color) -> Evaluation> =
-            vec![material_difference, evaluate_checkmate, evaluate_draw];
+    /// periodically checks the clock and the stop signal, once the search is aborted every
+    /// search function should return
+    fn check_search_aborted(&mut self) -> bool {
+        let total_nodes = self.stats.nodes + self.stats.quiescence_nodes;
+        if total_nodes.is_multiple_of(TIME_CHECK_INTERVAL) {
+            self.check_ponder_hit();
+            if self.timer.is_out_of_time() || self.stop_signal.is_stopped() {
+                self.search_aborted = true;
+            }
+        }
+        self.search_aborted
+    }
 
-        let mut eval_summed = Evaluation::Additive(0);
-        for strategy in strategies {
-            eval_summed = eval_summed + strategy(game_state, self.color);
+    /// whether the position occurred before: once within the search is enough, the side to move
+    /// could simply repeat it again. Before the root it has to be a real threefold repetition.
+    /// Only positions since the last capture or pawn move can match, and it takes at least
+    /// 4 plies to get back to the same position.
+    fn is_repetition(&self, key: u64, halfmoves: u32) -> bool {
+        let len = self.key_history.len();
+        let mut occurrences = 0;
+        for distance in (4..=(halfmoves as usize).min(len)).step_by(2) {
+            let index = len - distance;
+            if self.key_history[index] != key {
+                continue;
+            }
+            occurrences += 1;
+            if index >= self.root_index || occurrences >= 2 {
+                return true;
+            }
         }
-        eval_summed.to_i32()
+        false
     }
-}
 
-//////////////////////////  STRATEGIES  /////////////////////////////////////////
+    /// draws are scored with contempt, as slightly bad for the engine. So it only settles for a
+    /// draw if it is worse off otherwise
+    fn draw_score(&self, game_state: &Chess) -> i32 {
+        match game_state.turn() == self.game.turn() {
+            true => -self.config.contempt,
+            false => self.config.contempt,
+        }
+    }
 
-/// Main "Tactics" strategy
-fn material_difference(game: &Chess, bot_color: Color) -> Evaluation {
-    let side = if bot_color == Color::White { 1 } else { -1 };
-    Evaluation::Additive(util::material_difference(game.board()) * side)
-}
+    /// once the opponent played the expected move, the ponder search continues as a regular
+    /// search with a time budget starting now
+    fn check_ponder_hit(&mut self) {
+        if self.pondering && !self.ponder_state.is_active() {
+            self.pondering = false;
+            self.timer = TimeManager::new(self.ponder_state.clock(), self.game.turn());
+            debug!(
+                "Switched ponder search to a budget of {:.2}s",
+                self.timer.optimum().as_secs_f32()
+            );
+        }
+    }
 
-// overwrite any strategy on draw to a 0 - Evaluation
-fn evaluate_draw(game: &Chess, _bot_color: Color) -> Evaluation {
-    match game.is_stalemate() || game.is_insufficient_material() {
-        true => Evaluation::Absolute(0),
-        false => Evaluation::Additive(0), // no-op
+    /// the actual evaluation function, which combines the expected positional value of each
+    /// configured strategy. The evaluation is from the perspective of the side to move
+    /// (negamax), converted to a search value at the given distance to the root.
+    fn evaluate_position(&mut self, game_state: &Chess, ply: usize) -> i32 {
+        let score = match &mut self.nnue {
+            Some(nnue) => nnue.evaluate(game_state),
+            None => {
+                let accumulator = self
+                    .accumulators
+                    .last()
+                    .expect("accumulator of the root position");
+                self.evaluator.evaluate(game_state, accumulator)
+            }
+        };
+        score.to_search_value(ply)
     }
-}
 
-fn evaluate_checkmate(game: &Chess, bot_color: Color) -> Evaluation {
-    match game.is_checkmate() {
-        true => Evaluation::Absolute(if game.turn() != bot_color {
-            MAX_EVAL - game.fullmoves().get() as i32 // prefers faster checkmates
-        } else {
-            MIN_EVAL + game.fullmoves().get() as i32 // prefers faster checkmates
-        }),
-        false => Evaluation::Additive(0), // no-op
+    /// updates the evaluation accumulators for a move just played on the child position. The
+    /// search copies positions instead of undoing moves, only the accumulators have a stack.
+    /// Debug builds check every update against a full recomputation.
+    fn make_move(&mut self, turn: Color, m: &Move, child: &Chess) {
+        let parent = self
+            .accumulators
+            .last()
+            .expect("accumulator of the root position");
+        let accumulator = parent.play(turn, m);
+        debug_assert_eq!(
+            accumulator,
+            Accumulator::new(child.board()),
+            "incremental evaluation drifted after {m}, resulting in {}",
+            Fen::from_position(child, EnPassantMode::Legal)
+        );
+        self.accumulators.push(accumulator);
+
+        if let Some(nnue) = &mut self.nnue {
+            nnue.make_move(turn, m);
+            debug_assert!(
+                nnue.is_consistent(child.board()),
+                "network accumulator drifted after {m}, resulting in {}",
+                Fen::from_position(child, EnPassantMode::Legal)
+            );
+        }
     }
-}
 
-/// funny
-#[allow(dead_code)]
-fn chaaaaaaarge(game: &Chess, bot_color: Color) -> Evaluation {
-    let root_rank = if bot_color == Color::White {
-        Rank::First
-    } else {
-        Rank::Eighth
-    };
-
-    let eval: u32 = game
-        .board()
-        .iter()
-        .filter(|(_, p)| p.color == bot_color)
-        .map(|(sq, _p)| sq.rank().distance(root_rank))
-        .sum();
-
-    Evaluation::Additive(eval as i32)
+    fn unmake_move(&mut self) {
+        self.accumulators.pop();
+        if let Some(nnue) = &mut self.nnue {
+            nnue.unmake_move();
+        }
+    }
 }
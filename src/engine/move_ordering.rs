@@ -0,0 +1,116 @@
+use shakmaty::{Chess, Color, Move, MoveList, Position};
+
+use crate::util;
+
+use super::{
+    see,
+    transposition::{NO_MOVE, PackedMove, pack_move},
+};
+
+// move categories, searched in this order
+const HASH_MOVE_SCORE: i32 = 1_000_000;
+const CAPTURE_SCORE: i32 = 100_000;
+const FIRST_KILLER_SCORE: i32 = 90_000;
+const SECOND_KILLER_SCORE: i32 = 89_000;
+/// history scores get halved once they reach this, so quiets always stay behind the killers
+const MAX_HISTORY: i32 = 50_000;
+/// captures which lose material according to SEE come last
+const LOSING_CAPTURE_SCORE: i32 = -100_000;
+
+/// sorts moves by how likely they are to be good, so alpha-beta can prune as early as possible
+pub struct MoveOrderer {
+    /// quiet moves that caused a beta-cutoff at the same ply in a sibling position
+    killers: Vec<[PackedMove; 2]>,
+    /// how often (and how deep) a quiet move from->to caused a cutoff for each side
+    history: Box<[[[i32; 64]; 64]; 2]>,
+}
+impl MoveOrderer {
+    pub fn new(max_ply: usize) -> Self {
+        Self {
+            killers: vec![[NO_MOVE; 2]; max_ply + 1],
+            history: Box::new([[[0; 64]; 64]; 2]),
+        }
+    }
+
+    /// killers are only meaningful within one search, history is kept but its influence decays
+    pub fn new_search(&mut self) {
+        self.killers.fill([NO_MOVE; 2]);
+        self.history
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .for_each(|h| *h /= 2);
+    }
+
+    /// hash/PV move first, then winning captures by MVV-LVA, then killers, then quiets by
+    /// history score and finally the captures losing material
+    pub fn order_moves(
+        &self,
+        position: &Chess,
+        moves: &mut MoveList,
+        hash_move: PackedMove,
+        ply: usize,
+    ) {
+        moves.sort_by_cached_key(|m| -self.score_move(position, m, hash_move, ply));
+    }
+
+    fn score_move(&self, position: &Chess, m: &Move, hash_move: PackedMove, ply: usize) -> i32 {
+        let packed = pack_move(m);
+        if packed == hash_move {
+            return HASH_MOVE_SCORE;
+        }
+
+        if m.is_capture() || m.is_promotion() {
+            // most valuable victim, least valuable attacker. Kings count as cheapest attacker,
+            // a legal king capture can't be recaptured
+            let victim = m.capture().map_or(0, util::piece_value);
+            let promotion = m.promotion().map_or(0, util::piece_value);
+            let mvv_lva = 10 * (victim + promotion) - util::piece_value(m.role());
+            return match see::see(position, m) >= 0 {
+                true => CAPTURE_SCORE + mvv_lva,
+                false => LOSING_CAPTURE_SCORE + mvv_lva,
+            };
+        }
+
+        // quiescence search can go deeper than killers are tracked
+        let killers = self.killers.get(ply).copied().unwrap_or([NO_MOVE; 2]);
+        if packed == killers[0] {
+            FIRST_KILLER_SCORE
+        } else if packed == killers[1] {
+            SECOND_KILLER_SCORE
+        } else {
+            self.history_score(m, position.turn())
+        }
+    }
+
+    /// remember a quiet move which refuted the opponent's previous move
+    pub fn record_cutoff(&mut self, m: &Move, depth: u8, ply: usize, turn: Color) {
+        if m.is_capture() || m.is_promotion() {
+            return; // already ordered well by MVV-LVA
+        }
+
+        let packed = pack_move(m);
+        if let Some(killers) = self.killers.get_mut(ply)
+            && killers[0] != packed
+        {
+            killers[1] = killers[0];
+            killers[0] = packed;
+        }
+
+        let from = m.from().unwrap_or(m.to());
+        let entry = &mut self.history[turn as usize][from as usize][m.to() as usize];
+        *entry += depth as i32 * depth as i32;
+        if *entry >= MAX_HISTORY {
+            self.history
+                .iter_mut()
+                .flatten()
+                .flatten()
+                .for_each(|h| *h /= 2);
+        }
+    }
+
+    fn history_score(&self, m: &Move, turn: Color) -> i32 {
+        let from = m.from().unwrap_or(m.to());
+        self.history[turn as usize][from as usize][m.to() as usize]
+    }
+}
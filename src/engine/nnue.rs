@@ -0,0 +1,416 @@
+use std::{fs, path::Path, sync::Arc};
+
+use anyhow::{Context, Result, bail, ensure};
+use shakmaty::{Board, Chess, Color, Move, Piece, Position, Square};
+
+use super::{Score, accumulator, strategy};
+
+const MAGIC: &[u8; 8] = b"RLBNNUE1";
+/// one input per piece (6 roles, own or opponent's) and square
+const FEATURES: usize = 2 * 6 * 64;
+/// activations are clipped to 0..=ACTIVATION_MAX, which is 1.0 in the quantized format
+const ACTIVATION_MAX: i32 = 127;
+/// dense layer weights are quantized with a factor of 64, undone after each layer
+const WEIGHT_SHIFT: u32 = 6;
+/// the raw network output divided by this is the evaluation in centipawns
+const OUTPUT_SCALE: i32 = 16;
+
+/// an efficiently updatable neural network: the first layer only depends on which piece is on
+/// which square, so its output (the accumulator) is updated move by move. Two small dense
+/// layers with clipped ReLU turn it into an evaluation. Everything is integer arithmetic.
+pub struct Network {
+    /// size of the accumulator per perspective
+    hidden: usize,
+    /// FEATURES x hidden, one column of the first layer per feature
+    feature_weights: Vec<i16>,
+    feature_biases: Vec<i16>,
+    /// input: both accumulators, the side to move's first
+    hidden_layer_1: DenseLayer,
+    hidden_layer_2: DenseLayer,
+    output: DenseLayer,
+}
+impl Network {
+    /// reads a network from a file in this format, all numbers little-endian:
+    ///
+    /// | field                                   | type            |
+    /// |-----------------------------------------|-----------------|
+    /// | magic `RLBNNUE1`                        | 8 bytes         |
+    /// | hidden size H, layer sizes L1, L2       | 3 x u32         |
+    /// | feature weights, feature-major          | 768 x H x i16   |
+    /// | feature biases                          | H x i16         |
+    /// | layer 1 weights, output-major           | L1 x 2H x i8    |
+    /// | layer 1 biases                          | L1 x i32        |
+    /// | layer 2 weights, output-major           | L2 x L1 x i8    |
+    /// | layer 2 biases                          | L2 x i32        |
+    /// | output weights                          | L2 x i8         |
+    /// | output bias                             | i32             |
+    ///
+    /// Feature index: `(relation * 6 + role) * 64 + square`, with relation 0 for the pieces of
+    /// the perspective's side and 1 for the opponent's, roles from pawn (0) to king (5) and
+    /// squares from a1 (0) to h8 (63), mirrored vertically for black's perspective.
+    /// Activations are clipped to 0..=127, dense layers are shifted right by 6 and the output
+    /// divided by 16 is the evaluation in centipawns for the side to move.
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).with_context(|| format!("could not read {}", path.display()))?;
+        Self::parse(&bytes).with_context(|| format!("invalid network file {}", path.display()))
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            bail!("not a network file (wrong magic bytes)");
+        }
+        let hidden = reader.read_u32()? as usize;
+        let layer_1 = reader.read_u32()? as usize;
+        let layer_2 = reader.read_u32()? as usize;
+        ensure!(
+            hidden > 0 && layer_1 > 0 && layer_2 > 0,
+            "layer sizes must not be 0"
+        );
+
+        let network = Self {
+            hidden,
+            feature_weights: reader.read_i16s(size(FEATURES, hidden)?)?,
+            feature_biases: reader.read_i16s(hidden)?,
+            hidden_layer_1: DenseLayer::read(&mut reader, size(2, hidden)?, layer_1)?,
+            hidden_layer_2: DenseLayer::read(&mut reader, layer_1, layer_2)?,
+            output: DenseLayer::read(&mut reader, layer_2, 1)?,
+        };
+        ensure!(
+            reader.bytes.is_empty(),
+            "{} unexpected bytes at the end",
+            reader.bytes.len()
+        );
+        Ok(network)
+    }
+
+    /// the accumulator computed from scratch
+    fn refresh(&self, board: &Board) -> Perspectives {
+        let mut perspectives = Perspectives {
+            white: self.feature_biases.clone(),
+            black: self.feature_biases.clone(),
+        };
+        for (square, piece) in board.iter() {
+            self.update(&mut perspectives, piece, square, 1);
+        }
+        perspectives
+    }
+
+    /// direction 1 for a piece arriving on the square, -1 for one leaving it
+    fn update(
+        &self,
+        perspectives: &mut Perspectives,
+        piece: Piece,
+        square: Square,
+        direction: i32,
+    ) {
+        for (perspective, values) in [
+            (Color::White, &mut perspectives.white),
+            (Color::Black, &mut perspectives.black),
+        ] {
+            let feature = feature_index(perspective, piece, square);
+            let column = &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden];
+            for (value, weight) in values.iter_mut().zip(column) {
+                *value = match direction > 0 {
+                    true => value.wrapping_add(*weight),
+                    false => value.wrapping_sub(*weight),
+                };
+            }
+        }
+    }
+}
+
+/// the first layer's output for both sides, each seeing the board as if they were white
+#[derive(Clone, PartialEq, Eq)]
+struct Perspectives {
+    white: Vec<i16>,
+    black: Vec<i16>,
+}
+
+struct DenseLayer {
+    inputs: usize,
+    /// outputs x inputs
+    weights: Vec<i8>,
+    biases: Vec<i32>,
+}
+impl DenseLayer {
+    fn read(reader: &mut Reader, inputs: usize, outputs: usize) -> Result<Self> {
+        Ok(Self {
+            inputs,
+            weights: reader.read_i8s(size(outputs, inputs)?)?,
+            biases: reader.read_i32s(outputs)?,
+        })
+    }
+
+    /// raw outputs, before the shift and the activation
+    fn forward(&self, input: &[u8], output: &mut [i32]) {
+        for ((value, bias), row) in output
+            .iter_mut()
+            .zip(&self.biases)
+            .zip(self.weights.chunks_exact(self.inputs))
+        {
+            *value = bias
+                + row
+                    .iter()
+                    .zip(input)
+                    .map(|(weight, x)| *weight as i32 * *x as i32)
+                    .sum::<i32>();
+        }
+    }
+}
+
+/// evaluates positions along a search path, keeping one accumulator per ply
+pub struct NnueEvaluator {
+    network: Arc<Network>,
+    stack: Vec<Perspectives>,
+    /// index of the current position's accumulator in the stack
+    ply: usize,
+    // buffers for the dense layers, so evaluating doesn't allocate
+    input: Vec<u8>,
+    layer_1: Vec<i32>,
+    layer_1_activations: Vec<u8>,
+    layer_2: Vec<i32>,
+    layer_2_activations: Vec<u8>,
+}
+impl NnueEvaluator {
+    pub fn new(network: Arc<Network>) -> Self {
+        let layer_1 = network.hidden_layer_1.biases.len();
+        let layer_2 = network.hidden_layer_2.biases.len();
+        Self {
+            stack: Vec::new(),
+            ply: 0,
+            input: vec![0; 2 * network.hidden],
+            layer_1: vec![0; layer_1],
+            layer_1_activations: vec![0; layer_1],
+            layer_2: vec![0; layer_2],
+            layer_2_activations: vec![0; layer_2],
+            network,
+        }
+    }
+
+    pub fn network(&self) -> Arc<Network> {
+        Arc::clone(&self.network)
+    }
+
+    /// starts over from the root position of a search
+    pub fn reset(&mut self, board: &Board) {
+        let root = self.network.refresh(board);
+        match self.stack.first_mut() {
+            Some(first) => *first = root,
+            None => self.stack.push(root),
+        }
+        self.ply = 0;
+    }
+
+    /// the accumulator after the side to move played the move, derived from the current one
+    pub fn make_move(&mut self, turn: Color, m: &Move) {
+        if self.stack.len() == self.ply + 1 {
+            let copy = self.stack[self.ply].clone();
+            self.stack.push(copy);
+        } else {
+            let (parents, children) = self.stack.split_at_mut(self.ply + 1);
+            children[0].clone_from(&parents[self.ply]);
+        }
+        self.ply += 1;
+
+        let network = &self.network;
+        let current = &mut self.stack[self.ply];
+        accumulator::piece_changes(turn, m, |piece, square, direction| {
+            network.update(current, piece, square, direction);
+        });
+    }
+
+    pub fn unmake_move(&mut self) {
+        self.ply -= 1;
+    }
+
+    /// whether the incrementally updated accumulator matches a full recomputation
+    pub fn is_consistent(&self, board: &Board) -> bool {
+        self.stack[self.ply] == self.network.refresh(board)
+    }
+
+    /// evaluation of the current position from the perspective of the side to move. The network
+    /// knows nothing about the rules, so mates and draws are handled here
+    pub fn evaluate(&mut self, game: &Chess) -> Score {
+        if let Some(score) = strategy::decided_by_rules(game) {
+            return score;
+        }
+
+        let perspectives = &self.stack[self.ply];
+        let (own, opponent) = match game.turn() {
+            Color::White => (&perspectives.white, &perspectives.black),
+            Color::Black => (&perspectives.black, &perspectives.white),
+        };
+        let hidden = self.network.hidden;
+        clipped_relu(own, &mut self.input[..hidden], 0);
+        clipped_relu(opponent, &mut self.input[hidden..], 0);
+
+        let network = &self.network;
+        network
+            .hidden_layer_1
+            .forward(&self.input, &mut self.layer_1);
+        clipped_relu(&self.layer_1, &mut self.layer_1_activations, WEIGHT_SHIFT);
+        network
+            .hidden_layer_2
+            .forward(&self.layer_1_activations, &mut self.layer_2);
+        clipped_relu(&self.layer_2, &mut self.layer_2_activations, WEIGHT_SHIFT);
+        let mut output = [0];
+        network
+            .output
+            .forward(&self.layer_2_activations, &mut output);
+        Score::Centipawns(output[0] / OUTPUT_SCALE)
+    }
+}
+
+fn clipped_relu<T: Copy + Into<i32>>(values: &[T], activations: &mut [u8], shift: u32) {
+    for (activation, value) in activations.iter_mut().zip(values) {
+        *activation = ((*value).into() >> shift).clamp(0, ACTIVATION_MAX) as u8;
+    }
+}
+
+fn feature_index(perspective: Color, piece: Piece, square: Square) -> usize {
+    let relation = if piece.color == perspective { 0 } else { 1 };
+    let square = match perspective {
+        Color::White => square as usize,
+        Color::Black => square as usize ^ 56,
+    };
+    (relation * 6 + piece.role as usize - 1) * 64 + square
+}
+
+/// product of sizes from the header, which a corrupt file could make overflow
+fn size(a: usize, b: usize) -> Result<usize> {
+    a.checked_mul(b).context("layer sizes too large")
+}
+
+/// reads little-endian numbers from the front of a byte slice
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < count {
+            bail!("file ends early");
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn read_i8s(&mut self, count: usize) -> Result<Vec<i8>> {
+        Ok(self.take(count)?.iter().map(|byte| *byte as i8).collect())
+    }
+
+    fn read_i16s(&mut self, count: usize) -> Result<Vec<i16>> {
+        Ok(self
+            .take(size(count, 2)?)?
+            .chunks_exact(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect())
+    }
+
+    fn read_i32s(&mut self, count: usize) -> Result<Vec<i32>> {
+        Ok(self
+            .take(size(count, 4)?)?
+            .chunks_exact(4)
+            .map(|bytes| i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+
+    /// H = 2, L1 = L2 = 1. The first accumulator value sums the perspective's own pieces, the
+    /// second the opponent's, each piece counting `4 * (role + 1) + rank + file`. The output is
+    /// `(32 * (layer 1 activation) - 1600) / 16` with layer 1 = `32 * (own - opponent's own) +
+    /// 3200` and layer 2 passing its input through
+    fn tiny_network() -> Network {
+        let mut bytes = MAGIC.to_vec();
+        for size in [2u32, 1, 1] {
+            bytes.extend_from_slice(&size.to_le_bytes());
+        }
+        for feature in 0..FEATURES {
+            let (relation, role, square) = (feature / 384, feature / 64 % 6, feature % 64);
+            for hidden in 0..2 {
+                let weight = match relation == hidden {
+                    true => 4 * (role as i16 + 1) + square as i16 / 8 + square as i16 % 8,
+                    false => 0,
+                };
+                bytes.extend_from_slice(&weight.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&[32, 0, (-32i8) as u8, 0]);
+        bytes.extend_from_slice(&3200i32.to_le_bytes());
+        bytes.push(64);
+        bytes.extend_from_slice(&0i32.to_le_bytes());
+        bytes.push(32);
+        bytes.extend_from_slice(&(-1600i32).to_le_bytes());
+        Network::parse(&bytes).unwrap()
+    }
+
+    #[test]
+    fn tiny_network_evaluates_as_computed_by_hand() {
+        let mut nnue = NnueEvaluator::new(Arc::new(tiny_network()));
+        // on the e-file (4), white sees its king (24 + 4) and pawn (4 + 3 + 4), black its king
+        // (24 + 4) and white's pieces mirrored: the king on the last rank (24 + 7 + 4) and the
+        // pawn on the fifth (4 + 4 + 4)
+        for (fen, expected) in [
+            // (32 * (39 - 28) + 3200) >> 6 = 55, 55 * 32 - 1600 = 160
+            ("4k3/8/8/8/4P3/8/8/4K3 w - - 0 1", 10),
+            // (32 * (28 - 39) + 3200) >> 6 = 44, 44 * 32 - 1600 = -192
+            ("4k3/8/8/8/4P3/8/8/4K3 b - - 0 1", -12),
+        ] {
+            let position = util::position(fen);
+            nnue.reset(position.board());
+            assert_eq!(
+                nnue.evaluate(&position),
+                Score::Centipawns(expected),
+                "{fen}"
+            );
+        }
+    }
+
+    #[test]
+    fn accumulator_stays_consistent_with_special_moves() {
+        let mut nnue = NnueEvaluator::new(Arc::new(tiny_network()));
+        for (fen, moves) in [
+            ("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", &["exd5"][..]),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", &["O-O", "O-O-O"]),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", &["exd6"]),
+            ("3rk3/4P3/8/8/8/8/8/4K3 w - - 0 1", &["exd8=Q+", "Kxd8"]),
+            ("4k3/8/8/8/8/8/p7/4K3 b - - 0 1", &["a1=N"]),
+        ] {
+            let root = util::position(fen);
+            let mut position = root.clone();
+            nnue.reset(position.board());
+            for san in moves {
+                let turn = position.turn();
+                let m = util::play_move(&mut position, san).unwrap();
+                nnue.make_move(turn, &m);
+                assert!(nnue.is_consistent(position.board()), "{fen} {san}");
+            }
+            for _ in moves {
+                nnue.unmake_move();
+            }
+            assert!(nnue.is_consistent(root.board()), "{fen}");
+        }
+    }
+
+    #[test]
+    fn huge_layer_sizes_are_an_error() {
+        for sizes in [[u32::MAX, 1, 1], [1, u32::MAX, u32::MAX]] {
+            let mut bytes = MAGIC.to_vec();
+            for size in sizes {
+                bytes.extend_from_slice(&size.to_le_bytes());
+            }
+            assert!(Network::parse(&bytes).is_err(), "{sizes:?}");
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use anyhow::{Context, Result, bail};
+
+use crate::util;
+
+use super::strategy::StrategySettings;
+
+/// names of the tunable piece values, pawn to queen
+const PIECE_NAMES: [&str; 5] = ["pawn", "knight", "bishop", "rook", "queen"];
+
+/// evaluation parameters the tuner writes and the engine loads at startup: piece values in
+/// centipawns and strategy weights in percent. Stored as one `name = value` per line, e.g.
+/// `piece_value.knight = 320` or `strategy.mobility = 85`. `#` starts a comment, missing
+/// entries keep their defaults
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Parameters {
+    /// pawn, knight, bishop, rook, queen
+    pub piece_values: [i32; 5],
+    /// by strategy name, in percent
+    pub strategy_weights: Vec<(String, i32)>,
+}
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            piece_values: [
+                util::PAWN_VALUE,
+                util::KNIGHT_VALUE,
+                util::BISHOP_VALUE,
+                util::ROOK_VALUE,
+                util::QUEEN_VALUE,
+            ],
+            strategy_weights: Vec::new(),
+        }
+    }
+}
+impl Parameters {
+    pub fn load(path: &Path) -> Result<Self> {
+        fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?
+            .parse()
+            .with_context(|| format!("invalid parameter file {}", path.display()))
+    }
+
+    /// the strategy weights as settings, more specific ones can still override them
+    pub fn strategy_settings(&self) -> StrategySettings {
+        StrategySettings::from_weights(self.strategy_weights.iter().cloned())
+    }
+}
+impl FromStr for Parameters {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parameters = Self::default();
+        for (number, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((name, value)) = line.split_once('=') else {
+                bail!("line {}: expected <name> = <value>", number + 1);
+            };
+            let (name, value) = (name.trim(), value.trim());
+            let value: i32 = value
+                .parse()
+                .with_context(|| format!("line {}: invalid value '{value}'", number + 1))?;
+
+            if let Some(piece) = name.strip_prefix("piece_value.") {
+                let Some(index) = PIECE_NAMES.iter().position(|known| *known == piece) else {
+                    bail!("line {}: unknown piece '{piece}'", number + 1);
+                };
+                parameters.piece_values[index] = value;
+            } else if let Some(strategy) = name.strip_prefix("strategy.") {
+                parameters
+                    .strategy_weights
+                    .push((strategy.to_string(), value));
+            } else {
+                bail!("line {}: unknown parameter '{name}'", number + 1);
+            }
+        }
+        Ok(parameters)
+    }
+}
+impl fmt::Display for Parameters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in PIECE_NAMES.iter().zip(self.piece_values) {
+            writeln!(f, "piece_value.{name} = {value}")?;
+        }
+        for (name, weight) in &self.strategy_weights {
+            writeln!(f, "strategy.{name} = {weight}")?;
+        }
+        Ok(())
+    }
+}
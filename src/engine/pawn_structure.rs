@@ -0,0 +1,158 @@
+use shakmaty::{
+    Bitboard, Board, Color, File, Piece, Square,
+    zobrist::{Zobrist64, ZobristValue},
+};
+
+use crate::util::{adjacent_files, ranks_in_front, relative_rank};
+
+use super::piece_square;
+
+/// number of entries in the pawn hash table, a power of two
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+// (middlegame, endgame) values, per pawn
+const DOUBLED: (i32, i32) = (-10, -20);
+const ISOLATED: (i32, i32) = (-10, -15);
+const BACKWARD: (i32, i32) = (-8, -10);
+/// by relative rank, for pawns defended by or standing next to another pawn
+const CONNECTED: [i32; 8] = [0, 5, 7, 10, 15, 25, 40, 0];
+/// by relative rank. Halved if the square in front of the pawn is occupied
+const PASSED_MIDDLEGAME: [i32; 8] = [0, 5, 10, 15, 25, 40, 70, 0];
+const PASSED_ENDGAME: [i32; 8] = [0, 10, 20, 35, 60, 100, 160, 0];
+
+/// zobrist key of the pawns only, the same for every position with the same pawn structure
+pub fn pawn_key(board: &Board) -> u64 {
+    board
+        .pawns()
+        .into_iter()
+        .filter_map(|square| board.piece_at(square).map(|piece| (square, piece)))
+        .fold(0, |key, (square, piece): (Square, Piece)| {
+            key ^ Zobrist64::zobrist_for_piece(square, piece).0
+        })
+}
+
+/// everything about a pawn structure that only depends on the pawns, from white's perspective
+#[derive(Clone, Copy)]
+pub struct PawnEntry {
+    key: u64,
+    middlegame: i32,
+    endgame: i32,
+    passed_pawns: Bitboard,
+}
+
+/// caches pawn structure evaluations. The pawn structure rarely changes within a search, so
+/// almost every lookup is a hit
+pub struct PawnHashTable {
+    entries: Vec<Option<PawnEntry>>,
+}
+impl PawnHashTable {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![None; PAWN_TABLE_SIZE],
+        }
+    }
+
+    pub fn probe(&self, key: u64) -> Option<PawnEntry> {
+        self.entries[key as usize & (PAWN_TABLE_SIZE - 1)].filter(|entry| entry.key == key)
+    }
+
+    pub fn store(&mut self, entry: PawnEntry) {
+        self.entries[entry.key as usize & (PAWN_TABLE_SIZE - 1)] = Some(entry);
+    }
+}
+
+/// scores doubled, isolated, backward and connected pawns and finds the passed pawns
+pub fn analyze(board: &Board, key: u64) -> PawnEntry {
+    let mut middlegame = 0;
+    let mut endgame = 0;
+    let mut passed_pawns = Bitboard::EMPTY;
+
+    for color in Color::ALL {
+        let side = if color == Color::White { 1 } else { -1 };
+        let own_pawns = board.pawns() & board.by_color(color);
+        let enemy_pawns = board.pawns() & board.by_color(color.other());
+
+        for file in File::ALL {
+            let count = (own_pawns & Bitboard::from_file(file)).count() as i32;
+            if count > 1 {
+                middlegame += side * DOUBLED.0 * (count - 1);
+                endgame += side * DOUBLED.1 * (count - 1);
+            }
+        }
+
+        for square in own_pawns {
+            let rank = relative_rank(square, color);
+            let neighbours = adjacent_files(square.file()) & own_pawns;
+
+            if neighbours.is_empty() {
+                middlegame += side * ISOLATED.0;
+                endgame += side * ISOLATED.1;
+            } else if is_backward(square, color, neighbours, enemy_pawns) {
+                middlegame += side * BACKWARD.0;
+                endgame += side * BACKWARD.1;
+            }
+
+            // defended by a pawn, or side by side with one
+            let supporters = shakmaty::attacks::pawn_attacks(color.other(), square) & own_pawns;
+            let phalanx = neighbours & Bitboard::from_rank(square.rank());
+            if supporters.any() || phalanx.any() {
+                middlegame += side * CONNECTED[rank];
+                endgame += side * CONNECTED[rank];
+            }
+
+            let blockers = (Bitboard::from_file(square.file()) | adjacent_files(square.file()))
+                & ranks_in_front(square, color)
+                & enemy_pawns;
+            if blockers.is_empty() {
+                passed_pawns.add(square);
+            }
+        }
+    }
+
+    PawnEntry {
+        key,
+        middlegame,
+        endgame,
+        passed_pawns,
+    }
+}
+
+/// the pawn structure score from the perspective of the given side, including passed pawns.
+/// Whether a passed pawn is blocked depends on the other pieces, so that isn't cached
+pub fn evaluate(board: &Board, entry: &PawnEntry, color: Color) -> i32 {
+    let mut middlegame = entry.middlegame;
+    let mut endgame = entry.endgame;
+    for square in entry.passed_pawns {
+        let Some(pawn_color) = board.color_at(square) else {
+            continue;
+        };
+        let side = if pawn_color == Color::White { 1 } else { -1 };
+        let rank = relative_rank(square, pawn_color);
+        let blocked = square
+            .offset(pawn_color.fold_wb(8, -8))
+            .is_some_and(|front| board.occupied().contains(front));
+        let divisor = if blocked { 2 } else { 1 };
+        middlegame += side * PASSED_MIDDLEGAME[rank] / divisor;
+        endgame += side * PASSED_ENDGAME[rank] / divisor;
+    }
+
+    let white_score = piece_square::taper(middlegame, endgame, piece_square::game_phase(board));
+    if color == Color::White {
+        white_score
+    } else {
+        -white_score
+    }
+}
+
+/// can't be supported by a neighbouring pawn anymore (they are all further advanced) and
+/// can't advance safely either, because an enemy pawn controls the square in front of it
+fn is_backward(square: Square, color: Color, neighbours: Bitboard, enemy_pawns: Bitboard) -> bool {
+    let supporters_behind = neighbours & !ranks_in_front(square, color);
+    if supporters_behind.any() {
+        return false;
+    }
+    let Some(front) = square.offset(color.fold_wb(8, -8)) else {
+        return false;
+    };
+    (shakmaty::attacks::pawn_attacks(color, front) & enemy_pawns).any()
+}
@@ -0,0 +1,116 @@
+use shakmaty::{Bitboard, Board, Color, Role, Square, attacks};
+
+use crate::util::{self, adjacent_files, ranks_in_front, relative_rank};
+
+/// per safe square, by role
+const MOBILITY: [i32; 6] = [0, 4, 5, 2, 1, 0];
+/// by role, only knights and bishops profit from outposts
+const OUTPOST: [i32; 6] = [0, 25, 15, 0, 0, 0];
+const BISHOP_PAIR: i32 = 30;
+const ROOK_OPEN_FILE: i32 = 20;
+const ROOK_SEMI_OPEN_FILE: i32 = 10;
+const ROOK_SEVENTH_RANK: i32 = 20;
+/// share of a trapped piece's value it is likely to lose, in percent
+const TRAPPED_PIECE_PERCENT: i32 = 25;
+
+// every term is from the perspective of the given side: own pieces minus the opponent's
+
+/// squares each piece can move to without being taken by a pawn
+pub fn mobility(board: &Board, color: Color) -> i32 {
+    side_difference(color, |side| {
+        pieces(board, side)
+            .map(|(square, role)| {
+                safe_squares(board, side, square).count() as i32 * MOBILITY[role as usize - 1]
+            })
+            .sum()
+    })
+}
+
+/// knights and bishops on the 4th to 6th rank (from the side's point of view), protected by a
+/// pawn and out of reach of the enemy pawns, can't be driven away anymore
+pub fn outposts(board: &Board, color: Color) -> i32 {
+    side_difference(color, |side| {
+        let own_pawns = board.pawns() & board.by_color(side);
+        let enemy_pawns = board.pawns() & board.by_color(side.other());
+        pieces(board, side)
+            .filter(|(square, _)| (3..=5).contains(&relative_rank(*square, side)))
+            .filter(|(square, _)| (attacks::pawn_attacks(side.other(), *square) & own_pawns).any())
+            .filter(|(square, _)| {
+                let attackers = adjacent_files(square.file()) & ranks_in_front(*square, side);
+                (attackers & enemy_pawns).is_empty()
+            })
+            .map(|(_, role)| OUTPOST[role as usize - 1])
+            .sum()
+    })
+}
+
+pub fn bishop_pair(board: &Board, color: Color) -> i32 {
+    side_difference(color, |side| {
+        match (board.bishops() & board.by_color(side)).count() >= 2 {
+            true => BISHOP_PAIR,
+            false => 0,
+        }
+    })
+}
+
+/// rooks on files without pawns (or at least without own pawns) and on the 7th rank, where the
+/// opponent's pawns usually are
+pub fn rook_placement(board: &Board, color: Color) -> i32 {
+    side_difference(color, |side| {
+        let own_pawns = board.pawns() & board.by_color(side);
+        (board.rooks() & board.by_color(side))
+            .into_iter()
+            .map(|square| {
+                let file = Bitboard::from_file(square.file());
+                let file_bonus = if (file & board.pawns()).is_empty() {
+                    ROOK_OPEN_FILE
+                } else if (file & own_pawns).is_empty() {
+                    ROOK_SEMI_OPEN_FILE
+                } else {
+                    0
+                };
+                let rank_bonus = match relative_rank(square, side) == 6 {
+                    true => ROOK_SEVENTH_RANK,
+                    false => 0,
+                };
+                file_bonus + rank_bonus
+            })
+            .sum()
+    })
+}
+
+/// attacked pieces without a single safe square are in danger of getting caught
+pub fn trapped_pieces(board: &Board, color: Color) -> i32 {
+    side_difference(color, |side| {
+        -pieces(board, side)
+            .filter(|(square, _)| safe_squares(board, side, *square).is_empty())
+            .filter(|(square, _)| {
+                board
+                    .attacks_to(*square, side.other(), board.occupied())
+                    .any()
+            })
+            .map(|(_, role)| util::piece_value(role) * TRAPPED_PIECE_PERCENT / 100)
+            .sum::<i32>()
+    })
+}
+
+fn side_difference(color: Color, term: impl Fn(Color) -> i32) -> i32 {
+    term(color) - term(color.other())
+}
+
+/// knights, bishops, rooks and queens of the given side
+fn pieces(board: &Board, color: Color) -> impl Iterator<Item = (Square, Role)> + '_ {
+    (board.by_color(color) & !board.pawns() & !board.kings())
+        .into_iter()
+        .filter_map(|square| board.role_at(square).map(|role| (square, role)))
+}
+
+fn safe_squares(board: &Board, color: Color, square: Square) -> Bitboard {
+    let enemy_pawns = board.pawns() & board.by_color(color.other());
+    let pawn_controlled = enemy_pawns
+        .into_iter()
+        .fold(Bitboard::EMPTY, |controlled, pawn| {
+            controlled | attacks::pawn_attacks(color.other(), pawn)
+        });
+    board.attacks_from(square) & !board.by_color(color) & !pawn_controlled
+}
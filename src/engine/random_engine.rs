@@ -1,8 +1,8 @@
-use super::Engine;
+use super::{Engine, GameClock, Score, SearchResult, StopSignal, StrategyScore};
 use anyhow::Result;
 use async_trait::async_trait;
 use rand::{Rng, rng};
-use shakmaty::{Chess, Color, Move, Position, uci::UciMove};
+use shakmaty::{Chess, Color, KnownOutcome, Position, uci::UciMove};
 
 pub struct RandomEngine {
     game: Chess,
@@ -35,13 +35,33 @@ impl Engine for RandomEngine {
         Ok(())
     }
 
-    async fn search(&mut self) -> Option<Move> {
+    fn update_clock(&mut self, _clock: GameClock) {}
+
+    fn stop_signal(&self) -> StopSignal {
+        StopSignal::default() // nothing to stop, searching is instant
+    }
+
+    fn prepare_search(&mut self) {}
+
+    fn start_pondering(&mut self) {}
+
+    fn game_over(&mut self, _outcome: KnownOutcome) {}
+
+    fn explain(&self, _position: &Chess) -> Vec<StrategyScore> {
+        Vec::new() // no evaluation at all
+    }
+
+    async fn search(&mut self) -> Option<SearchResult> {
         let legals = self.game.legal_moves();
         if legals.is_empty() {
             return None;
         }
         let rng = rng().random_range(0..legals.len());
 
-        legals.get(rng).cloned()
+        legals.get(rng).map(|m| SearchResult {
+            best_move: *m,
+            eval: Score::Centipawns(0),
+            principal_variation: vec![*m],
+        })
     }
 }
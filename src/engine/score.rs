@@ -0,0 +1,153 @@
+use std::{cmp::max_by_key, fmt, ops::Add};
+
+/// search values are plain i32 for cheap alpha-beta arithmetic. A forced mate is stored as
+/// MATE_VALUE minus its distance to the root in plies, so faster mates score higher
+pub const MATE_VALUE: i32 = 1_000_000;
+/// bigger than any search value, used as initial search window
+pub const INFINITY: i32 = MATE_VALUE + 1;
+/// search values within this many plies of MATE_VALUE are forced mates
+const MAX_MATE_PLIES: i32 = 1_000;
+/// a win known from the endgame tablebases, which don't tell the distance to mate. Reduced by
+/// the distance to the root like mates, but below all of them
+pub const TABLEBASE_WIN: i32 = MATE_VALUE - MAX_MATE_PLIES - 1;
+/// search values from here up are tablebase wins or mates, which depend on the distance to the
+/// root. Centipawn evaluations stay below
+const MIN_DECISIVE_VALUE: i32 = TABLEBASE_WIN - MAX_MATE_PLIES;
+
+/// evaluation of a position from the perspective of the side to move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+    Centipawns(i32),
+    /// forced mate in that many plies. Positive if the side to move delivers it, otherwise the
+    /// side to move gets mated (0 = already checkmated)
+    Mate(i32),
+    /// won for the side to move according to the endgame tablebases, which are reached in that
+    /// many plies. They don't tell how far away the mate is
+    TablebaseWin(i32),
+    /// lost for the side to move according to the tablebases, reached in that many plies
+    TablebaseLoss(i32),
+    /// exact draw, like stalemate or insufficient material
+    Draw,
+}
+impl Score {
+    /// converts the score of a position at the given distance to the root into a search value
+    pub fn to_search_value(self, ply: usize) -> i32 {
+        let ply = ply as i32;
+        match self {
+            Score::Centipawns(cp) => cp.clamp(-MIN_DECISIVE_VALUE + 1, MIN_DECISIVE_VALUE - 1),
+            Score::Mate(plies) if plies > 0 => MATE_VALUE - ply - plies,
+            Score::Mate(plies) => -MATE_VALUE + ply - plies,
+            Score::TablebaseWin(plies) => TABLEBASE_WIN - ply - plies,
+            Score::TablebaseLoss(plies) => -TABLEBASE_WIN + ply + plies,
+            Score::Draw => 0,
+        }
+    }
+
+    /// converts a search value of the root position back into a score
+    pub fn from_search_value(value: i32) -> Self {
+        if value >= MATE_VALUE - MAX_MATE_PLIES {
+            Score::Mate(MATE_VALUE - value)
+        } else if value <= -MATE_VALUE + MAX_MATE_PLIES {
+            Score::Mate(-(MATE_VALUE + value))
+        } else if value >= MIN_DECISIVE_VALUE {
+            Score::TablebaseWin(TABLEBASE_WIN - value)
+        } else if value <= -MIN_DECISIVE_VALUE {
+            Score::TablebaseLoss(TABLEBASE_WIN + value)
+        } else {
+            Score::Centipawns(value)
+        }
+    }
+}
+
+/// strategies are combined by adding them up. Mates, tablebase results and draws are absolute
+/// though, they overwrite any centipawn evaluation
+impl Add for Score {
+    type Output = Score;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        match (self, rhs) {
+            (Score::Centipawns(i), Score::Centipawns(j)) => Score::Centipawns(i + j),
+            (Score::Centipawns(_), absolute) | (absolute, Score::Centipawns(_)) => absolute,
+            // choose the least extreme evaluation, the slowest mate
+            (Score::Draw, _) | (_, Score::Draw) => Score::Draw,
+            (Score::Mate(i), Score::Mate(j)) => Score::Mate(max_by_key(i, j, |plies| plies.abs())),
+            // a mate is more precise than the tablebases
+            (mate @ Score::Mate(_), _) | (_, mate @ Score::Mate(_)) => mate,
+            (tablebase, _) => tablebase,
+        }
+    }
+}
+
+/// "+1.34" in pawns, or "#5"/"#-5" for mates in moves (not plies), like lichess does.
+/// Tablebase results are "TB win" and "TB loss"
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Score::Centipawns(cp) => write!(f, "{:+.2}", cp as f32 / 100.0),
+            Score::Mate(plies) if plies > 0 => write!(f, "#{}", (plies + 1) / 2),
+            Score::Mate(plies) => write!(f, "#-{}", (-plies + 1) / 2),
+            Score::TablebaseWin(_) => write!(f, "TB win"),
+            Score::TablebaseLoss(_) => write!(f, "TB loss"),
+            Score::Draw => write!(f, "0.00"),
+        }
+    }
+}
+
+pub fn is_mate_value(value: i32) -> bool {
+    value.abs() >= MATE_VALUE - MAX_MATE_PLIES
+}
+
+/// the transposition table stores mates and tablebase wins relative to the position instead of
+/// the root, so they stay correct when the position is reached at a different ply
+pub fn to_tt_value(value: i32, ply: usize) -> i32 {
+    match value {
+        v if v >= MIN_DECISIVE_VALUE => v + ply as i32,
+        v if v <= -MIN_DECISIVE_VALUE => v - ply as i32,
+        v => v,
+    }
+}
+
+pub fn from_tt_value(value: i32, ply: usize) -> i32 {
+    match value {
+        v if v >= MIN_DECISIVE_VALUE => v - ply as i32,
+        v if v <= -MIN_DECISIVE_VALUE => v + ply as i32,
+        v => v,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decisive_values_are_relative_to_the_position_in_the_table() {
+        for value in [TABLEBASE_WIN - 3, MATE_VALUE - 7] {
+            let stored = to_tt_value(value, 3);
+            assert_eq!(from_tt_value(stored, 5), value - 2);
+            assert_eq!(from_tt_value(to_tt_value(-value, 3), 5), -value + 2);
+        }
+        assert_eq!(from_tt_value(to_tt_value(250, 3), 5), 250);
+    }
+
+    #[test]
+    fn tablebase_results_are_shown_as_such() {
+        for (value, score, text) in [
+            (TABLEBASE_WIN, Score::TablebaseWin(0), "TB win"),
+            (TABLEBASE_WIN - 5, Score::TablebaseWin(5), "TB win"),
+            (-TABLEBASE_WIN + 3, Score::TablebaseLoss(3), "TB loss"),
+            (MATE_VALUE - 3, Score::Mate(3), "#2"),
+            (250, Score::Centipawns(250), "+2.50"),
+        ] {
+            assert_eq!(Score::from_search_value(value), score);
+            assert_eq!(score.to_search_value(0), value);
+            assert_eq!(score.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn centipawns_stay_below_tablebase_wins() {
+        let deepest_tablebase_win = TABLEBASE_WIN - MAX_MATE_PLIES + 1;
+        assert!(Score::Centipawns(i32::MAX).to_search_value(0) < deepest_tablebase_win);
+        assert!(Score::Centipawns(i32::MIN).to_search_value(0) > -deepest_tablebase_win);
+    }
+}
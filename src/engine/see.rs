@@ -0,0 +1,196 @@
+use shakmaty::{Bitboard, Board, Chess, Color, Move, Position, Rank, Role, Square};
+
+use crate::util;
+
+/// a king can only take part in an exchange as the very last capture
+const KING_VALUE: i32 = 20_000;
+/// there are only 32 pieces, an exchange can't be longer than that
+const MAX_EXCHANGE_LENGTH: usize = 32;
+
+/// static exchange evaluation: the material the side to move wins (or loses) with this move,
+/// assuming both sides keep recapturing on the target square with their least valuable piece
+/// and stop as soon as that doesn't pay off anymore. Sliders behind other attackers (x-rays)
+/// join once the way is clear. The move promotes to its own piece, pawns recapturing on the last
+/// rank count as queens. Pins are ignored.
+pub fn see(position: &Chess, m: &Move) -> i32 {
+    let board = position.board();
+    let Some(from) = m.from() else {
+        return 0; // drops don't exist in standard chess
+    };
+
+    let mut occupied = board.occupied();
+    let captured = match m {
+        Move::EnPassant { from, to } => {
+            // the captured pawn isn't on the target square
+            occupied.discard(Square::from_coords(to.file(), from.rank()));
+            util::piece_value(Role::Pawn)
+        }
+        _ => m.capture().map_or(0, util::piece_value),
+    };
+
+    exchange(
+        board,
+        occupied,
+        m.to(),
+        (from, m.role()),
+        m.promotion(),
+        position.turn(),
+        captured,
+    )
+}
+
+/// the best the given side can win by capturing the piece on the square with its least
+/// valuable attacker, 0 if it has no capture there that pays off
+pub fn threat(board: &Board, square: Square, attacker: Color) -> i32 {
+    let Some(victim) = board.role_at(square) else {
+        return 0;
+    };
+    let attackers = board.attacks_to(square, attacker, board.occupied());
+    match least_valuable_attacker(board, attackers) {
+        Some(first) => exchange(
+            board,
+            board.occupied(),
+            square,
+            first,
+            None,
+            attacker,
+            util::piece_value(victim),
+        )
+        .max(0),
+        None => 0,
+    }
+}
+
+/// plays out the capture sequence on the target square, starting with the given piece of the
+/// given side, and returns its result for that side. A first attacker that is a promoting pawn
+/// becomes the given piece, a queen if there is none
+fn exchange(
+    board: &Board,
+    mut occupied: Bitboard,
+    target: Square,
+    first_attacker: (Square, Role),
+    mut promotion: Option<Role>,
+    side: Color,
+    captured: i32,
+) -> i32 {
+    // gains[i]: material balance for the side making capture i, if the opponent stops after it
+    let mut gains = [0; MAX_EXCHANGE_LENGTH];
+    gains[0] = captured;
+
+    let (mut from, mut role) = first_attacker;
+    let mut side = side;
+    let mut depth = 0;
+    loop {
+        let mut value_on_target = match role {
+            Role::King => KING_VALUE,
+            role => util::piece_value(role),
+        };
+        if role == Role::Pawn && target.rank() == side.fold_wb(Rank::Eighth, Rank::First) {
+            value_on_target = util::piece_value(promotion.take().unwrap_or(Role::Queen));
+            gains[depth] += value_on_target - util::piece_value(Role::Pawn);
+        }
+
+        // removing the piece from the board uncovers the sliders behind it
+        occupied.discard(from);
+        side = side.other();
+        let attackers = board.attacks_to(target, side, occupied) & occupied;
+        let Some(next_attacker) = least_valuable_attacker(board, attackers) else {
+            break;
+        };
+        if depth + 1 >= MAX_EXCHANGE_LENGTH {
+            break;
+        }
+
+        depth += 1;
+        gains[depth] = value_on_target - gains[depth - 1];
+        (from, role) = next_attacker;
+    }
+
+    // every side may stop capturing instead, so go back and pick the better option
+    while depth > 0 {
+        gains[depth - 1] = -(-gains[depth - 1]).max(gains[depth]);
+        depth -= 1;
+    }
+    gains[0]
+}
+
+fn least_valuable_attacker(board: &Board, attackers: Bitboard) -> Option<(Square, Role)> {
+    Role::ALL.into_iter().find_map(|role| {
+        (attackers & board.by_role(role))
+            .first()
+            .map(|square| (square, role))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use shakmaty::uci::UciMove;
+
+    use super::*;
+
+    fn see_of(fen: &str, uci: &str) -> i32 {
+        let position = util::position(fen);
+        let m = uci.parse::<UciMove>().unwrap().to_move(&position).unwrap();
+        see(&position, &m)
+    }
+
+    #[test]
+    fn standard_exchanges() {
+        let value = util::piece_value;
+        for (fen, uci, expected) in [
+            // pawn takes an undefended knight
+            (
+                "4k3/8/8/3n4/4P3/8/8/4K3 w - - 0 1",
+                "e4d5",
+                value(Role::Knight),
+            ),
+            // pawn takes a knight defended by a pawn
+            (
+                "4k3/8/4p3/3n4/4P3/8/8/4K3 w - - 0 1",
+                "e4d5",
+                value(Role::Knight) - value(Role::Pawn),
+            ),
+            // knight takes a pawn defended by a pawn
+            (
+                "4k3/8/2p5/3p4/8/4N3/8/4K3 w - - 0 1",
+                "e3d5",
+                value(Role::Pawn) - value(Role::Knight),
+            ),
+            // the rook behind the first one recaptures through it
+            (
+                "4r1k1/8/8/4p3/8/8/4R3/4R1K1 w - - 0 1",
+                "e2e5",
+                value(Role::Pawn),
+            ),
+            // without the battery the rook is lost
+            (
+                "4r1k1/8/8/4p3/8/8/4R3/6K1 w - - 0 1",
+                "e2e5",
+                value(Role::Pawn) - value(Role::Rook),
+            ),
+            // a quiet move to an attacked square
+            (
+                "4k3/8/8/3p4/8/2N5/8/4K3 w - - 0 1",
+                "c3e4",
+                -value(Role::Knight),
+            ),
+        ] {
+            assert_eq!(see_of(fen, uci), expected, "{fen} {uci}");
+        }
+    }
+
+    #[test]
+    fn promotions_count_as_the_promoted_piece() {
+        let value = util::piece_value;
+        let fen = "3r3k/4P3/8/8/8/8/8/4K3 w - - 0 1";
+        let gain = |role| value(Role::Rook) + value(role) - value(Role::Pawn);
+        assert_eq!(see_of(fen, "e7d8q"), gain(Role::Queen));
+        assert_eq!(see_of(fen, "e7d8n"), gain(Role::Knight));
+        // recaptured by the king
+        let defended = "3r4/2k1P3/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(
+            see_of(defended, "e7d8n"),
+            value(Role::Rook) - value(Role::Pawn)
+        );
+    }
+}
@@ -0,0 +1,591 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, bail};
+use log::warn;
+use shakmaty::{Board, Chess, Color, Position, Rank};
+
+use super::{
+    Score,
+    accumulator::Accumulator,
+    endgame, king_safety,
+    pawn_structure::{self, PawnHashTable},
+    piece_activity, see,
+};
+
+/// weight of a strategy unless configured otherwise, in percent
+const DEFAULT_WEIGHT: i32 = 100;
+/// only every n-th evaluation is timed, reading the clock for every strategy at every leaf
+/// would cost more than some of the strategies themselves
+const TIMING_SAMPLE_INTERVAL: u64 = 64;
+
+/// one term of the evaluation. Most strategies should only nudge the score a tiny bit compared
+/// to the material_difference strategy (pawn-win = +100), so they apply only in case of not
+/// having the oportunity to win material directly. Checkmate and draws aren't strategies, they
+/// are rules and always overwrite the strategies (see `decided_by_rules`).
+pub trait Strategy: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// in percent of the raw score. Mates and draws aren't weighted
+    fn weight(&self) -> i32;
+
+    fn enabled(&self) -> bool;
+
+    /// raw score from the perspective of the given side. The accumulator holds the material and
+    /// piece-square sums of the position, kept up to date by the search
+    fn evaluate(&mut self, game: &Chess, accumulator: &Accumulator, color: Color) -> Score;
+
+    /// share of lookups a strategy with its own cache could answer from it since the last
+    /// reset, in percent
+    fn cache_hit_rate(&self) -> Option<f32> {
+        None
+    }
+
+    /// starts counting the statistics of a new search
+    fn reset_stats(&mut self) {}
+
+    /// share of the combined evaluation that is left, in percent. For strategies that know
+    /// better than the others how winnable a position is
+    fn scale(&self, _game: &Chess) -> i32 {
+        100
+    }
+}
+
+/// a strategy without any state of its own
+struct FunctionStrategy {
+    name: &'static str,
+    weight: i32,
+    enabled: bool,
+    function: StrategyFunction,
+}
+#[derive(Clone, Copy)]
+enum StrategyFunction {
+    /// looks at the whole position
+    Position(fn(&Chess, Color) -> Score),
+    /// only needs the incrementally updated sums
+    Incremental(fn(&Accumulator, Color) -> Score),
+}
+impl FunctionStrategy {
+    fn boxed(
+        name: &'static str,
+        function: StrategyFunction,
+        enabled_by_default: bool,
+        settings: &StrategySettings,
+    ) -> Box<dyn Strategy> {
+        Box::new(Self {
+            name,
+            weight: settings.weight(name),
+            enabled: settings.enabled(name, enabled_by_default),
+            function,
+        })
+    }
+}
+impl Strategy for FunctionStrategy {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn weight(&self) -> i32 {
+        self.weight
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn evaluate(&mut self, game: &Chess, accumulator: &Accumulator, color: Color) -> Score {
+        match self.function {
+            StrategyFunction::Position(function) => function(game, color),
+            StrategyFunction::Incremental(function) => function(accumulator, color),
+        }
+    }
+}
+
+/// doubled, isolated, backward, connected and passed pawns. Unlike the other strategies it
+/// owns a cache, the pawn hash table
+struct PawnStructure {
+    weight: i32,
+    enabled: bool,
+    /// not shared between threads, every thread quickly fills its own
+    table: PawnHashTable,
+    probes: u64,
+    hits: u64,
+}
+impl PawnStructure {
+    const NAME: &'static str = "pawn_structure";
+
+    fn boxed(settings: &StrategySettings) -> Box<dyn Strategy> {
+        Box::new(Self {
+            weight: settings.weight(Self::NAME),
+            enabled: settings.enabled(Self::NAME, true),
+            table: PawnHashTable::new(),
+            probes: 0,
+            hits: 0,
+        })
+    }
+}
+impl Strategy for PawnStructure {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn weight(&self) -> i32 {
+        self.weight
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn evaluate(&mut self, game: &Chess, _accumulator: &Accumulator, color: Color) -> Score {
+        let board = game.board();
+        let key = pawn_structure::pawn_key(board);
+        self.probes += 1;
+        let entry = match self.table.probe(key) {
+            Some(entry) => {
+                self.hits += 1;
+                entry
+            }
+            None => {
+                let entry = pawn_structure::analyze(board, key);
+                self.table.store(entry);
+                entry
+            }
+        };
+        Score::Centipawns(pawn_structure::evaluate(board, &entry, color))
+    }
+
+    fn cache_hit_rate(&self) -> Option<f32> {
+        match self.probes {
+            0 => Some(0.0),
+            probes => Some(self.hits as f32 / probes as f32 * 100.0),
+        }
+    }
+
+    /// only the counters, the table is kept between searches
+    fn reset_stats(&mut self) {
+        self.probes = 0;
+        self.hits = 0;
+    }
+}
+
+/// basic mates and endgames that are drawn despite the material: pushes a bare king to the edge
+/// when there's enough material to mate it, and scales the evaluation of known draws towards 0
+struct EndgameKnowledge {
+    weight: i32,
+    enabled: bool,
+}
+impl EndgameKnowledge {
+    const NAME: &'static str = "endgame_knowledge";
+
+    fn boxed(settings: &StrategySettings) -> Box<dyn Strategy> {
+        Box::new(Self {
+            weight: settings.weight(Self::NAME),
+            enabled: settings.enabled(Self::NAME, true),
+        })
+    }
+}
+impl Strategy for EndgameKnowledge {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn weight(&self) -> i32 {
+        self.weight
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn evaluate(&mut self, game: &Chess, _accumulator: &Accumulator, color: Color) -> Score {
+        Score::Centipawns(endgame::mop_up(game.board(), color))
+    }
+
+    fn scale(&self, game: &Chess) -> i32 {
+        endgame::scale(game.board())
+    }
+}
+
+/// calls of a strategy since the last reset, and the time spent in the sampled ones
+#[derive(Clone, Copy, Default)]
+struct StrategyStats {
+    calls: u64,
+    timed_calls: u64,
+    time: Duration,
+}
+
+/// contribution of a single strategy to an evaluation
+#[derive(Clone, Copy, Debug)]
+pub struct StrategyScore {
+    pub name: &'static str,
+    pub raw: Score,
+    /// in percent
+    pub weight: i32,
+    /// what the strategy added to the evaluation, including how it scaled the others
+    pub weighted: Score,
+}
+impl fmt::Display for StrategyScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} ({} x {}%)",
+            self.name, self.weighted, self.raw, self.weight
+        )
+    }
+}
+
+/// the configured strategies, combined to the evaluation of a position
+pub struct Evaluator {
+    strategies: Vec<Box<dyn Strategy>>,
+    /// by index of the strategy
+    stats: Vec<StrategyStats>,
+    /// since the last reset, to pick the ones that get timed
+    evaluations: u64,
+}
+impl Evaluator {
+    pub fn new(settings: &StrategySettings) -> Self {
+        use StrategyFunction::{Incremental, Position};
+        let strategies = vec![
+            FunctionStrategy::boxed(
+                "material_difference",
+                Incremental(material_difference),
+                true,
+                settings,
+            ),
+            FunctionStrategy::boxed(
+                "piece_square_tables",
+                Incremental(piece_square_tables),
+                true,
+                settings,
+            ),
+            FunctionStrategy::boxed("king_safety", Position(king_safety), true, settings),
+            FunctionStrategy::boxed("hanging_pieces", Position(hanging_pieces), true, settings),
+            FunctionStrategy::boxed("mobility", Position(mobility), true, settings),
+            FunctionStrategy::boxed("outposts", Position(outposts), true, settings),
+            FunctionStrategy::boxed("bishop_pair", Position(bishop_pair), true, settings),
+            FunctionStrategy::boxed("rook_placement", Position(rook_placement), true, settings),
+            FunctionStrategy::boxed("trapped_pieces", Position(trapped_pieces), true, settings),
+            PawnStructure::boxed(settings),
+            EndgameKnowledge::boxed(settings),
+            FunctionStrategy::boxed("chaaaaaaarge", Position(chaaaaaaarge), false, settings),
+        ];
+        for (name, _) in &settings.overrides {
+            if !strategies.iter().any(|strategy| strategy.name() == name) {
+                warn!("Ignoring setting for unknown strategy '{name}'");
+            }
+        }
+
+        Self {
+            stats: vec![StrategyStats::default(); strategies.len()],
+            strategies,
+            evaluations: 0,
+        }
+    }
+
+    /// sum of all enabled strategies (Add operator of Score is adjusted), from the perspective
+    /// of the side to move. The accumulator has to belong to the position
+    pub fn evaluate(&mut self, game: &Chess, accumulator: &Accumulator) -> Score {
+        if let Some(score) = decided_by_rules(game) {
+            return score;
+        }
+        let mut eval = Score::Centipawns(0);
+        let mut scale = 100;
+        let timed = self.evaluations.is_multiple_of(TIMING_SAMPLE_INTERVAL);
+        self.evaluations += 1;
+        for (strategy, stats) in self.strategies.iter_mut().zip(&mut self.stats) {
+            if !strategy.enabled() {
+                continue;
+            }
+            let start = timed.then(Instant::now);
+            let raw = strategy.evaluate(game, accumulator, game.turn());
+            let score = weighted(raw, strategy.weight());
+            scale = scale.min(strategy.scale(game));
+            if let Some(start) = start {
+                stats.time += start.elapsed();
+                stats.timed_calls += 1;
+            }
+            stats.calls += 1;
+            eval = eval + score;
+        }
+        weighted(eval, scale)
+    }
+
+    /// share of the summed evaluation that is left after scaling, in percent
+    pub fn scale(&self, game: &Chess) -> i32 {
+        self.strategies
+            .iter()
+            .filter(|strategy| strategy.enabled())
+            .map(|strategy| strategy.scale(game))
+            .min()
+            .unwrap_or(100)
+    }
+
+    /// what each enabled strategy contributes to the evaluation of the position, from the
+    /// perspective of the side to move. Not counted in the timing statistics
+    pub fn explain(&mut self, game: &Chess) -> Vec<StrategyScore> {
+        if let Some(score) = decided_by_rules(game) {
+            return vec![StrategyScore {
+                name: "rules",
+                raw: score,
+                weight: 100,
+                weighted: score,
+            }];
+        }
+        let accumulator = Accumulator::new(game.board());
+        let mut scores = self
+            .strategies
+            .iter_mut()
+            .filter(|strategy| strategy.enabled())
+            .map(|strategy| {
+                let raw = strategy.evaluate(game, &accumulator, game.turn());
+                StrategyScore {
+                    name: strategy.name(),
+                    raw,
+                    weight: strategy.weight(),
+                    weighted: weighted(raw, strategy.weight()),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // what scaling took off the total is attributed to the strategy that scaled
+        let scaling = self
+            .strategies
+            .iter()
+            .filter(|strategy| strategy.enabled())
+            .map(|strategy| (strategy.name(), strategy.scale(game)))
+            .min_by_key(|(_, scale)| *scale)
+            .filter(|(_, scale)| *scale < 100);
+        if let Some((name, scale)) = scaling {
+            let total = scores
+                .iter()
+                .fold(Score::Centipawns(0), |total, score| total + score.weighted);
+            if let (Score::Centipawns(total), Some(score)) =
+                (total, scores.iter_mut().find(|score| score.name == name))
+            {
+                score.weighted = score.weighted + Score::Centipawns(total * scale / 100 - total);
+            }
+        }
+        scores
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats.fill(StrategyStats::default());
+        self.evaluations = 0;
+        for strategy in &mut self.strategies {
+            strategy.reset_stats();
+        }
+    }
+
+    /// of the pawn structure's hash table since the last reset, 0 if it's disabled
+    pub fn pawn_table_hit_rate(&self) -> f32 {
+        self.strategies
+            .iter()
+            .find(|strategy| strategy.name() == PawnStructure::NAME && strategy.enabled())
+            .and_then(|strategy| strategy.cache_hit_rate())
+            .unwrap_or(0.0)
+    }
+
+    /// calls and average time per enabled strategy, e.g. "mobility: 52k calls, 1.3µs". The
+    /// average is taken over the sampled calls
+    pub fn stats_summary(&self) -> String {
+        self.strategies
+            .iter()
+            .zip(&self.stats)
+            .filter(|(strategy, _)| strategy.enabled())
+            .map(|(strategy, stats)| {
+                let average = stats
+                    .time
+                    .checked_div(stats.timed_calls as u32)
+                    .unwrap_or_default();
+                let cache = match strategy.cache_hit_rate() {
+                    Some(rate) => format!(", cache hits: {rate:.1}%"),
+                    None => String::new(),
+                };
+                format!(
+                    "{}: {} calls, {:.2?}{cache}",
+                    strategy.name(),
+                    stats.calls,
+                    average
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+}
+
+fn weighted(score: Score, weight: i32) -> Score {
+    match score {
+        Score::Centipawns(centipawns) => Score::Centipawns(centipawns * weight / 100),
+        score => score,
+    }
+}
+
+/// per-strategy deviations from the defaults, parsed from a list like
+/// `mobility=50,outposts=off,chaaaaaaarge=on`. A weight also enables a strategy
+#[derive(Clone, Debug, Default)]
+pub struct StrategySettings {
+    overrides: Vec<(String, StrategyOverride)>,
+}
+#[derive(Clone, Copy, Debug)]
+enum StrategyOverride {
+    Weight(i32),
+    Enabled(bool),
+}
+impl StrategySettings {
+    pub fn from_weights(weights: impl IntoIterator<Item = (String, i32)>) -> Self {
+        Self {
+            overrides: weights
+                .into_iter()
+                .map(|(name, weight)| (name, StrategyOverride::Weight(weight)))
+                .collect(),
+        }
+    }
+
+    /// these settings, with the given ones taking precedence
+    pub fn overridden_by(mut self, other: StrategySettings) -> Self {
+        self.overrides.extend(other.overrides);
+        self
+    }
+
+    fn weight(&self, name: &str) -> i32 {
+        self.overrides
+            .iter()
+            .rev()
+            .find_map(|(strategy, setting)| match setting {
+                StrategyOverride::Weight(weight) if strategy == name => Some(*weight),
+                _ => None,
+            })
+            .unwrap_or(DEFAULT_WEIGHT)
+    }
+
+    fn enabled(&self, name: &str, default: bool) -> bool {
+        self.overrides
+            .iter()
+            .rev()
+            .find_map(|(strategy, setting)| match setting {
+                _ if strategy != name => None,
+                StrategyOverride::Weight(_) => Some(true),
+                StrategyOverride::Enabled(enabled) => Some(*enabled),
+            })
+            .unwrap_or(default)
+    }
+}
+impl FromStr for StrategySettings {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut overrides = Vec::new();
+        for setting in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let Some((name, value)) = setting.split_once('=') else {
+                bail!("expected <strategy>=<weight|on|off>, got '{setting}'");
+            };
+            let value = match value.trim() {
+                "on" => StrategyOverride::Enabled(true),
+                "off" => StrategyOverride::Enabled(false),
+                weight => StrategyOverride::Weight(
+                    weight
+                        .parse()
+                        .with_context(|| format!("invalid weight '{weight}' for {name}"))?,
+                ),
+            };
+            overrides.push((name.trim().to_string(), value));
+        }
+        Ok(Self { overrides })
+    }
+}
+
+//////////////////////////  STRATEGIES  /////////////////////////////////////////
+
+/// Main "Tactics" strategy
+fn material_difference(accumulator: &Accumulator, color: Color) -> Score {
+    Score::Centipawns(accumulator.material(color))
+}
+
+/// positional value of every piece depending on its square, e.g. knights belong in the center
+/// and the king in a corner, at least until the endgame
+fn piece_square_tables(accumulator: &Accumulator, color: Color) -> Score {
+    Score::Centipawns(accumulator.piece_square(color))
+}
+
+/// pawn shield, open files and attackers around the king, fades out towards the endgame
+fn king_safety(game: &Chess, color: Color) -> Score {
+    Score::Centipawns(king_safety::evaluate(game, color))
+}
+
+/// pieces the other side could win material on, the opponent's count for the given side
+fn hanging_pieces(game: &Chess, color: Color) -> Score {
+    let board = game.board();
+    Score::Centipawns(hanging_loss(board, color.other()) - hanging_loss(board, color))
+}
+
+/// what the pieces of the given side under threat are likely to cost it. Only one of them can
+/// be saved with the next move (and the quiescence search takes care of captures), but every
+/// further one is probably lost
+fn hanging_loss(board: &Board, color: Color) -> i32 {
+    let threats = (board.by_color(color) & !board.kings())
+        .into_iter()
+        .map(|square| see::threat(board, square, color.other()))
+        .filter(|threat| *threat > 0)
+        .collect::<Vec<_>>();
+    let biggest_threat = threats.iter().copied().max().unwrap_or(0);
+    (threats.iter().sum::<i32>() - biggest_threat) / 2
+}
+
+/// safe squares the pieces can move to
+fn mobility(game: &Chess, color: Color) -> Score {
+    Score::Centipawns(piece_activity::mobility(game.board(), color))
+}
+
+/// knights and bishops that can't be chased away by pawns
+fn outposts(game: &Chess, color: Color) -> Score {
+    Score::Centipawns(piece_activity::outposts(game.board(), color))
+}
+
+fn bishop_pair(game: &Chess, color: Color) -> Score {
+    Score::Centipawns(piece_activity::bishop_pair(game.board(), color))
+}
+
+/// rooks on open files and on the 7th rank
+fn rook_placement(game: &Chess, color: Color) -> Score {
+    Score::Centipawns(piece_activity::rook_placement(game.board(), color))
+}
+
+/// attacked pieces that have nowhere to go
+fn trapped_pieces(game: &Chess, color: Color) -> Score {
+    Score::Centipawns(piece_activity::trapped_pieces(game.board(), color))
+}
+
+/// checkmate and draws by the rules of the game, from the perspective of the side to move. None
+/// if the game goes on. The search turns the mate into a mate in N plies from the root, so faster
+/// mates are preferred
+pub fn decided_by_rules(game: &Chess) -> Option<Score> {
+    if game.is_checkmate() {
+        Some(Score::Mate(0))
+    } else if game.is_stalemate() || game.is_insufficient_material() {
+        Some(Score::Draw)
+    } else {
+        None
+    }
+}
+
+/// funny, disabled unless configured
+fn chaaaaaaarge(game: &Chess, color: Color) -> Score {
+    let root_rank = if color == Color::White {
+        Rank::First
+    } else {
+        Rank::Eighth
+    };
+
+    let eval: u32 = game
+        .board()
+        .iter()
+        .filter(|(_, p)| p.color == color)
+        .map(|(sq, _p)| sq.rank().distance(root_rank))
+        .sum();
+
+    Score::Centipawns(eval as i32)
+}
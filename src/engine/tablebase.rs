@@ -0,0 +1,94 @@
+use std::path::Path;
+
+use anyhow::{Context, Result, ensure};
+use shakmaty::{Chess, Move, Position};
+use shakmaty_syzygy::{AmbiguousWdl, Dtz, Tablebase, Wdl};
+
+/// Syzygy endgame tablebases: the exact outcome of every position with few enough pieces,
+/// taking the fifty-move rule into account. Only the files of a directory are listed when
+/// loading, the tables are opened when they're first probed
+pub struct Tablebases {
+    tables: Tablebase<Chess>,
+}
+impl Tablebases {
+    /// adds all table files (`.rtbw` for WDL, `.rtbz` for DTZ) in the directory
+    pub fn load(directory: &Path) -> Result<Self> {
+        let mut tables = Tablebase::new();
+        let count = tables
+            .add_directory(directory)
+            .with_context(|| format!("could not read tablebases from {}", directory.display()))?;
+        ensure!(count > 0, "no tablebase files in {}", directory.display());
+        Ok(Self { tables })
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        self.tables.max_pieces()
+    }
+
+    fn covers(&self, position: &Chess) -> bool {
+        position.board().occupied().count() <= self.max_pieces()
+    }
+
+    /// win, draw or loss for the side to move. Only meant for positions right after a capture or
+    /// pawn move, otherwise the fifty-move counter might already have changed the outcome. None
+    /// if the position has too many pieces, castling rights or its table is missing
+    pub fn probe_wdl(&self, position: &Chess) -> Option<Wdl> {
+        if !self.covers(position) || position.halfmoves() != 0 {
+            return None;
+        }
+        self.tables.probe_wdl_after_zeroing(position).ok()
+    }
+
+    /// the move that keeps the best outcome and gets closest to zeroing the fifty-move counter,
+    /// with that outcome and the distance to zeroing of the position itself, for the side to
+    /// move. Needs the DTZ tables as well
+    pub fn best_move(&self, position: &Chess) -> Option<(Move, Wdl, Dtz)> {
+        if !self.covers(position) {
+            return None;
+        }
+        let (best_move, _) = self.tables.best_move(position).ok()??;
+        // the DTZ that comes with the move belongs to the position after it, from the
+        // opponent's point of view
+        let dtz = self.tables.probe_dtz(position).ok()?;
+        let wdl = match AmbiguousWdl::from_dtz_and_halfmoves(dtz, position.halfmoves()) {
+            AmbiguousWdl::Win | AmbiguousWdl::MaybeWin => Wdl::Win,
+            AmbiguousWdl::Loss | AmbiguousWdl::MaybeLoss => Wdl::Loss,
+            AmbiguousWdl::CursedWin => Wdl::CursedWin,
+            AmbiguousWdl::BlessedLoss => Wdl::BlessedLoss,
+            AmbiguousWdl::Draw => Wdl::Draw,
+        };
+        Some((best_move, wdl, dtz.ignore_rounding()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util;
+
+    #[test]
+    #[ignore = "needs the KQvK and KRvK tables (.rtbw and .rtbz) in BOT_SYZYGY_PATH"]
+    fn best_move_reports_the_outcome_for_the_side_to_move() {
+        let directory = std::env::var("BOT_SYZYGY_PATH").expect("BOT_SYZYGY_PATH");
+        let tablebases = Tablebases::load(Path::new(&directory)).unwrap();
+
+        for (fen, expected) in [
+            ("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", Wdl::Win),
+            ("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", Wdl::Win),
+            ("4k3/8/8/8/8/8/8/3QK3 b - - 0 1", Wdl::Loss),
+            ("4k3/8/8/8/8/8/8/R3K3 b - - 0 1", Wdl::Loss),
+        ] {
+            let position = util::position(fen);
+            assert_eq!(tablebases.probe_wdl(&position), Some(expected), "{fen}");
+
+            let (best_move, wdl, dtz) = tablebases.best_move(&position).unwrap();
+            assert_eq!(wdl, expected, "{fen}");
+            assert_eq!(dtz.signum(), expected.signum(), "{fen}");
+            // the move keeps the outcome, the opponent gets the opposite one
+            let mut after = position.clone();
+            after.play_unchecked(best_move);
+            let after_wdl = tablebases.tables.probe_wdl(&after).unwrap().signum();
+            assert_eq!(after_wdl, -expected.signum(), "{fen} {best_move}");
+        }
+    }
+}
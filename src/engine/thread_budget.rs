@@ -0,0 +1,65 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+/// search threads currently running, summed over all engine instances (= all running games)
+static ACTIVE_SEARCH_THREADS: AtomicUsize = AtomicUsize::new(0);
+/// games currently being played, every one of them may want to search at any time
+static ACTIVE_GAMES: AtomicUsize = AtomicUsize::new(0);
+
+pub fn available_cores() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// counts a game as being played for as long as it's alive, so the cores can be split between
+/// the games
+pub struct GameRegistration;
+impl GameRegistration {
+    pub fn new() -> Self {
+        ACTIVE_GAMES.fetch_add(1, Ordering::Relaxed);
+        Self
+    }
+}
+impl Drop for GameRegistration {
+    fn drop(&mut self) {
+        ACTIVE_GAMES.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// share of the host's CPU cores reserved for one search. Every search gets at least its main
+/// thread, helper threads are only granted up to the game's fair share of the cores and while
+/// cores are left, so several games searching at the same time don't oversubscribe the CPU.
+/// The cores are given back on drop.
+pub struct ThreadReservation {
+    threads: usize,
+}
+impl ThreadReservation {
+    pub fn acquire(max_threads: usize) -> Self {
+        let cores = available_cores();
+        let fair_share = cores / ACTIVE_GAMES.load(Ordering::Relaxed).max(1);
+        let max_threads = max_threads.min(fair_share);
+        let mut active = ACTIVE_SEARCH_THREADS.load(Ordering::Relaxed);
+        loop {
+            let threads = max_threads.min(cores.saturating_sub(active)).max(1);
+            match ACTIVE_SEARCH_THREADS.compare_exchange_weak(
+                active,
+                active + threads,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Self { threads },
+                Err(current) => active = current,
+            }
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+}
+impl Drop for ThreadReservation {
+    fn drop(&mut self) {
+        ACTIVE_SEARCH_THREADS.fetch_sub(self.threads, Ordering::Relaxed);
+    }
+}
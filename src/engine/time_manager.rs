@@ -0,0 +1,103 @@
+use std::time::{Duration, Instant};
+
+use shakmaty::Color;
+
+/// assumed number of moves the remaining clock time has to last for
+const EXPECTED_MOVES_TO_GO: u32 = 30;
+/// safety margin for network latency between deciding on a move and lichess receiving it
+const MOVE_OVERHEAD: Duration = Duration::from_millis(150);
+/// budget used when lichess didn't send any clock information (yet)
+const FALLBACK_MOVE_TIME: Duration = Duration::from_secs(3);
+/// never think longer than this, even in correspondence or very long games
+const MAX_MOVE_TIME: Duration = Duration::from_secs(30);
+const MIN_MOVE_TIME: Duration = Duration::from_millis(10);
+
+/// remaining clock time and increment of both players, as reported by lichess
+#[derive(Clone, Copy, Debug)]
+pub struct GameClock {
+    pub white_time: Duration,
+    pub black_time: Duration,
+    pub white_increment: Duration,
+    pub black_increment: Duration,
+}
+impl GameClock {
+    /// lichess reports wtime/btime/winc/binc in milliseconds
+    pub fn from_millis(wtime: u64, btime: u64, winc: u32, binc: u32) -> Self {
+        Self {
+            white_time: Duration::from_millis(wtime),
+            black_time: Duration::from_millis(btime),
+            white_increment: Duration::from_millis(winc as u64),
+            black_increment: Duration::from_millis(binc as u64),
+        }
+    }
+
+    pub fn time_left(&self, color: Color) -> Duration {
+        color.fold_wb(self.white_time, self.black_time)
+    }
+
+    pub fn increment(&self, color: Color) -> Duration {
+        color.fold_wb(self.white_increment, self.black_increment)
+    }
+}
+
+/// allocates a time budget for a single move and keeps track of the time spent on it
+#[derive(Clone, Copy)]
+pub struct TimeManager {
+    start: Instant,
+    /// the time we would like to spend on this move
+    optimum: Duration,
+    /// the search has to be stopped at this point, even in the middle of an iteration
+    maximum: Duration,
+}
+impl TimeManager {
+    pub fn new(clock: Option<GameClock>, color: Color) -> Self {
+        let (optimum, maximum) = match clock {
+            Some(clock) => {
+                let remaining = clock.time_left(color).saturating_sub(MOVE_OVERHEAD);
+                let increment = clock.increment(color);
+
+                let base = remaining / EXPECTED_MOVES_TO_GO + increment * 3 / 4;
+                let maximum = (base * 3).min(remaining / 4).min(MAX_MOVE_TIME);
+                (base.min(maximum), maximum)
+            }
+            None => (FALLBACK_MOVE_TIME, FALLBACK_MOVE_TIME),
+        };
+
+        Self {
+            start: Instant::now(),
+            optimum: optimum.max(MIN_MOVE_TIME),
+            maximum: maximum.max(MIN_MOVE_TIME),
+        }
+    }
+
+    /// no time limit, the search runs until it gets stopped (used for pondering)
+    pub fn infinite() -> Self {
+        Self {
+            start: Instant::now(),
+            optimum: Duration::MAX,
+            maximum: Duration::MAX,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn optimum(&self) -> Duration {
+        self.optimum
+    }
+
+    pub fn maximum(&self) -> Duration {
+        self.maximum
+    }
+
+    /// the next iteration usually takes several times as long as all previous ones together,
+    /// so only start one if there is a realistic chance of finishing it within the budget
+    pub fn should_start_iteration(&self) -> bool {
+        self.elapsed() < self.optimum / 2
+    }
+
+    pub fn is_out_of_time(&self) -> bool {
+        self.elapsed() >= self.maximum
+    }
+}
@@ -0,0 +1,149 @@
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
+use shakmaty::{
+    Chess, EnPassantMode, Move,
+    zobrist::{Zobrist64, ZobristHash},
+};
+
+/// compact move representation stored in the table: 6 bits from-square, 6 bits to-square and
+/// 3 bits promotion role. 0 means "no move" (a1a1 can't be a legal move)
+pub type PackedMove = u16;
+pub const NO_MOVE: PackedMove = 0;
+
+pub fn pack_move(m: &Move) -> PackedMove {
+    let from = m.from().map_or(0, |sq| sq as u16);
+    let to = m.to() as u16;
+    let promotion = m.promotion().map_or(0, |role| role as u16);
+    from | (to << 6) | (promotion << 12)
+}
+
+pub fn position_key(position: &Chess) -> u64 {
+    position.zobrist_hash::<Zobrist64>(EnPassantMode::Legal).0
+}
+
+/// how the stored score relates to the true value of the position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    /// all moves were searched, the score is exact
+    Exact,
+    /// a cutoff happened, the true score is at least this high
+    Lower,
+    /// no move reached alpha, the true score is at most this high
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TtEntry {
+    pub depth: u8,
+    pub bound: Bound,
+    pub score: i32,
+    pub best_move: PackedMove,
+}
+impl TtEntry {
+    // layout: score (32 bit) | depth (8 bit) | bound (2 bit) | move (16 bit) | generation (6 bit)
+    fn pack(&self, generation: u8) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 0u64,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        (self.score as u32 as u64)
+            | ((self.depth as u64) << 32)
+            | (bound << 40)
+            | ((self.best_move as u64) << 42)
+            | (((generation & GENERATION_MASK) as u64) << 58)
+    }
+
+    fn unpack(data: u64) -> Self {
+        let bound = match (data >> 40) & 0b11 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        };
+        Self {
+            score: data as u32 as i32,
+            depth: (data >> 32) as u8,
+            bound,
+            best_move: (data >> 42) as u16,
+        }
+    }
+}
+
+const GENERATION_MASK: u8 = 0b11_1111;
+fn generation_of(data: u64) -> u8 {
+    (data >> 58) as u8 & GENERATION_MASK
+}
+
+/// one slot of the table. The key is stored xor'ed with the data, so an entry that was torn by
+/// two concurrent writers simply fails the key check instead of returning garbage
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+/// fixed-size hash table caching search results by Zobrist key, so transpositions (the same
+/// position reached by different move orders) don't have to be searched again.
+/// Lock-free, so it can be shared by all threads of a search.
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    mask: usize,
+    generation: AtomicU8,
+}
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let bytes = size_mb.max(1) * 1024 * 1024;
+        // round down to a power of two, so the index is a cheap bit-mask
+        let max_slots = bytes / size_of::<Slot>();
+        let num_slots = 1usize << max_slots.ilog2();
+
+        Self {
+            slots: (0..num_slots).map(|_| Slot::default()).collect(),
+            mask: num_slots - 1,
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    /// marks the start of a new search, entries of older searches get replaced more eagerly
+    pub fn new_search(&self) {
+        let generation = (self.generation() + 1) & GENERATION_MASK;
+        self.generation.store(generation, Ordering::Relaxed);
+    }
+
+    fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        let slot = &self.slots[key as usize & self.mask];
+        let data = slot.data.load(Ordering::Relaxed);
+        match slot.key.load(Ordering::Relaxed) ^ data == key {
+            true => Some(TtEntry::unpack(data)),
+            false => None,
+        }
+    }
+
+    pub fn store(&self, key: u64, entry: TtEntry) {
+        let slot = &self.slots[key as usize & self.mask];
+        let old_data = slot.data.load(Ordering::Relaxed);
+        let old_key = slot.key.load(Ordering::Relaxed) ^ old_data;
+
+        // depth-preferred replacement, but never keep results of previous searches around forever
+        let generation = self.generation();
+        let replace =
+            generation_of(old_data) != generation || entry.depth >= TtEntry::unpack(old_data).depth;
+        if !replace {
+            return;
+        }
+
+        let mut entry = entry;
+        if entry.best_move == NO_MOVE && old_key == key {
+            // keep the known best move, it's still the best guess for move ordering
+            entry.best_move = TtEntry::unpack(old_data).best_move;
+        }
+
+        let data = entry.pack(generation);
+        slot.key.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+}
@@ -1,28 +1,33 @@
-mod engine;
-mod util;
-
-use crate::engine::Engine;
 use anyhow::{Result, bail};
 use chrono::Local;
 use fern::Dispatch;
-use futures::StreamExt;
+use futures::{Stream, StreamExt, stream::Fuse};
 use licheszter::{
     client::Licheszter,
     models::{
         board::{BoardState, Event},
         challenge::ChallengeStatus,
-        chat::ChatRoom,
-        game::{GameEventInfo, GameStatus, VariantMode},
+        chat::{ChatLine, ChatRoom},
+        game::{GameEventInfo, GameState, GameStatus, VariantMode},
     },
 };
 use log::LevelFilter;
 use log::{debug, error, info};
-use shakmaty::{CastlingMode, Chess, Color, Position, Square, fen::Fen};
+use rusty_lichess_bot::{
+    engine::{self, Engine, EngineConfig, GameClock, Score, SearchResult},
+    util::{self, parse_uci_move, parse_uci_moves},
+};
+use shakmaty::{CastlingMode, Chess, Color, KnownOutcome, Position, Square, fen::Fen};
 use std::io;
-use std::{env, str::FromStr, sync::Arc};
-use util::{parse_uci_move, parse_uci_moves};
+use std::{cmp::Reverse, collections::VecDeque, env, pin::Pin, str::FromStr, sync::Arc};
 
 const MAX_SIMULTANEOUS_GAMES: usize = 3;
+/// strategies named in the answer to "!explain", lichess chat messages are short
+const CHAT_EXPLAIN_STRATEGIES: usize = 3;
+
+type GameStream = Fuse<Pin<Box<dyn Stream<Item = licheszter::error::Result<BoardState>> + Send>>>;
+/// game stream events which arrived while the engine was busy searching
+type EventBacklog = VecDeque<licheszter::error::Result<BoardState>>;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -39,6 +44,11 @@ async fn main() -> anyhow::Result<()> {
 
     let client = Arc::new(Licheszter::builder().with_authentication(token).build());
 
+    // read once, every game gets a copy. The piece values are global and must not change while
+    // games are searching
+    let config = Arc::new(EngineConfig::from_env());
+    util::set_piece_values(config.piece_values);
+
     info!("Bot connected - listening for events...");
 
     let mut waiting_challenges = Vec::new();
@@ -75,7 +85,7 @@ async fn main() -> anyhow::Result<()> {
                         game_id, game_info.opponent.username
                     );
 
-                    tokio::spawn(spawn_engine(client.clone(), game_info));
+                    tokio::spawn(spawn_engine(client.clone(), game_info, config.clone()));
                 }
                 Event::GameFinish { game } => {
                     info!("[{}] GameEnd", game.id);
@@ -104,23 +114,39 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn spawn_engine(client: Arc<Licheszter>, game_id: GameEventInfo) {
-    match spawn_engine_internal(client, game_id).await {
+async fn spawn_engine(client: Arc<Licheszter>, game_id: GameEventInfo, config: Arc<EngineConfig>) {
+    match spawn_engine_internal(client, game_id, config).await {
         Ok(()) => info!("engine finished! exiting & dropping engine instance ..."),
         Err(e) => error!("engine failed because, {e}"),
     };
 }
 
-async fn spawn_engine_internal(client: Arc<Licheszter>, game_id: GameEventInfo) -> Result<()> {
+async fn spawn_engine_internal(
+    client: Arc<Licheszter>,
+    game_id: GameEventInfo,
+    config: Arc<EngineConfig>,
+) -> Result<()> {
     let mut engine: Option<Box<dyn Engine>> = None;
     let mut stream = client
         .bot_game_connect(&game_id.id)
         .await
-        .expect("Error while feathing game state stream.");
+        .expect("Error while feathing game state stream.")
+        .fuse();
+    let mut backlog = EventBacklog::new();
+    // evaluation of the bot's last move, for the !eval chat command
+    let mut last_score: Option<Score> = None;
 
     debug!("got stream handle for game {}", game_id.id);
     // in-game event loop
-    while let Some(item) = stream.next().await {
+    loop {
+        // events that arrived during a search come first
+        let item = match backlog.pop_front() {
+            Some(item) => item,
+            None => match stream.next().await {
+                Some(item) => item,
+                None => break,
+            },
+        };
         match item {
             Ok(state) => {
                 match state {
@@ -150,7 +176,7 @@ async fn spawn_engine_internal(client: Arc<Licheszter>, game_id: GameEventInfo)
                         };
 
                         // setup engine with the default board of the current game mode
-                        engine = Some(engine::init_engine(game, bot_color));
+                        engine = Some(engine::init_engine(game, bot_color, (*config).clone()));
 
                         match &mut engine {
                             Some(engine) => {
@@ -168,8 +194,24 @@ async fn spawn_engine_internal(client: Arc<Licheszter>, game_id: GameEventInfo)
                                     }
                                 }
 
+                                let state = &game_full.state;
+                                engine.update_clock(GameClock::from_millis(
+                                    state.wtime,
+                                    state.btime,
+                                    state.winc,
+                                    state.binc,
+                                ));
+
                                 if engine.is_my_turn() {
-                                    bot_play_move(client.clone(), game_id.clone(), engine).await?;
+                                    last_score = bot_play_move(
+                                        client.clone(),
+                                        game_id.clone(),
+                                        engine,
+                                        &mut stream,
+                                        &mut backlog,
+                                    )
+                                    .await?
+                                    .or(last_score);
                                 }
                             }
                             None => {
@@ -199,10 +241,26 @@ async fn spawn_engine_internal(client: Arc<Licheszter>, game_id: GameEventInfo)
                                         // update position to current
                                         let uci_move = parse_uci_move(last_move)?;
                                         engine.update_board(uci_move).await?;
+                                        engine.update_clock(GameClock::from_millis(
+                                            game_state.wtime,
+                                            game_state.btime,
+                                            game_state.winc,
+                                            game_state.binc,
+                                        ));
 
                                         if engine.is_my_turn() {
-                                            bot_play_move(client.clone(), game_id.clone(), engine)
-                                                .await?;
+                                            last_score = bot_play_move(
+                                                client.clone(),
+                                                game_id.clone(),
+                                                engine,
+                                                &mut stream,
+                                                &mut backlog,
+                                            )
+                                            .await?
+                                            .or(last_score);
+                                        } else {
+                                            // our own move came back, the opponent is thinking now
+                                            engine.start_pondering();
                                         }
                                     }
                                     None => {
@@ -217,10 +275,26 @@ async fn spawn_engine_internal(client: Arc<Licheszter>, game_id: GameEventInfo)
                                 }
                             }
                             status => {
-                                info!("received game status {:?}", status)
+                                info!("received game status {:?}", status);
+                                if let Some(engine) = &mut engine {
+                                    match game_outcome(&game_state) {
+                                        Some(outcome) => engine.game_over(outcome),
+                                        None => engine.stop_signal().stop(), // in case it's pondering
+                                    }
+                                }
                             }
                         }
                     }
+                    BoardState::ChatLine(chat_line) => {
+                        answer_chat_command(
+                            client.clone(),
+                            &game_id,
+                            &chat_line,
+                            last_score,
+                            engine.as_deref(),
+                        )
+                        .await;
+                    }
                     game_state => {
                         info!(
                             "[{}] Other board state recieved: {:?}",
@@ -304,14 +378,102 @@ fn log_move(last_move: &str, in_game_state: &Chess, for_game: &str) -> Result<()
     Ok(())
 }
 
+/// runs the engine's search while still listening to the game stream, so the search can be
+/// stopped once the game is over (resignation, abort, flagging, ...). Events received in the
+/// meantime are queued in the backlog. Returns None if the game ended during the search.
+async fn search_while_listening(
+    engine: &mut Box<dyn Engine>,
+    stream: &mut GameStream,
+    backlog: &mut EventBacklog,
+) -> Option<Option<SearchResult>> {
+    let stop_signal = engine.stop_signal();
+    engine.prepare_search();
+    let mut search = engine.search();
+    let mut game_over = false;
+    let mut stream_closed = false;
+
+    let result = loop {
+        tokio::select! {
+            result = &mut search => break result,
+            item = stream.next(), if !stream_closed => match item {
+                Some(item) => {
+                    if let Ok(BoardState::GameState(state)) = &item
+                        && state.status != GameStatus::Started
+                    {
+                        info!("Game ended ({:?}) while searching, stopping search", state.status);
+                        stop_signal.stop();
+                        game_over = true;
+                    }
+                    backlog.push_back(item);
+                }
+                None => {
+                    info!("Game stream closed while searching, stopping search");
+                    stop_signal.stop();
+                    stream_closed = true;
+                    game_over = true;
+                }
+            }
+        }
+    };
+
+    match game_over {
+        true => None,
+        false => Some(result),
+    }
+}
+
+/// the result of a finished game, None if it never really started or the result doesn't say
+/// anything about the moves played (cheat detection, unknown reasons)
+fn game_outcome(state: &GameState) -> Option<KnownOutcome> {
+    match (&state.status, &state.winner) {
+        (
+            GameStatus::Created
+            | GameStatus::Started
+            | GameStatus::Aborted
+            | GameStatus::NoStart
+            | GameStatus::Cheat
+            | GameStatus::UnknownFinish,
+            _,
+        ) => None,
+        (_, Some(licheszter::models::game::Color::White)) => Some(KnownOutcome::Decisive {
+            winner: Color::White,
+        }),
+        (_, Some(licheszter::models::game::Color::Black)) => Some(KnownOutcome::Decisive {
+            winner: Color::Black,
+        }),
+        // timeouts without a winner: the opponent couldn't have mated anymore
+        (
+            GameStatus::Draw
+            | GameStatus::Stalemate
+            | GameStatus::InsufficientMaterialClaim
+            | GameStatus::Timeout
+            | GameStatus::OutOfTime,
+            None,
+        ) => Some(KnownOutcome::Draw),
+        _ => None,
+    }
+}
+
 async fn bot_play_move(
     client: Arc<Licheszter>,
     game_id: GameEventInfo,
     engine: &mut Box<dyn Engine>,
-) -> Result<(), anyhow::Error> {
-    if let Some(chosen_move) = engine.search().await {
+    stream: &mut GameStream,
+    backlog: &mut EventBacklog,
+) -> Result<Option<Score>, anyhow::Error> {
+    let Some(search_result) = search_while_listening(engine, stream, backlog).await else {
+        return Ok(None); // nobody is waiting for our move anymore
+    };
+
+    if let Some(result) = search_result {
         // convert move back to uci and send to lichess.org
-        let uci_move = chosen_move.to_uci(CastlingMode::Standard).to_string();
+        let uci_move = result.best_move.to_uci(CastlingMode::Standard).to_string();
+        debug!(
+            "[{}] Playing {uci_move} (eval: {}), expecting {}",
+            game_id.id,
+            result.eval,
+            util::format_line(engine.get_game_state(), &result.principal_variation)
+        );
 
         // retry if failed
         let retries = 3;
@@ -336,6 +498,7 @@ async fn bot_play_move(
                 }
             }
         }
+        Ok(Some(result.eval))
     } else {
         abort_game_cleanly_after_error(
             client,
@@ -343,10 +506,50 @@ async fn bot_play_move(
             "Engine could not compute a move to play!",
             Some("I couldn't find a move to play. I will resign now"),
         )
-        .await?
+        .await?;
+        Ok(None)
     }
+}
 
-    Ok(())
+/// spectators (and the opponent) can ask for the engine's opinion with "!eval", and for the
+/// strategies that matter most in the current position with "!explain"
+async fn answer_chat_command(
+    client: Arc<Licheszter>,
+    game_id: &GameEventInfo,
+    chat_line: &ChatLine,
+    last_score: Option<Score>,
+    engine: Option<&dyn Engine>,
+) {
+    let answer = match (chat_line.text.trim(), engine) {
+        ("!eval", _) => match last_score {
+            Some(score) => format!("My evaluation after my last move: {score}"),
+            None => "I haven't evaluated this game yet".to_string(),
+        },
+        ("!explain", Some(engine)) => {
+            let position = engine.get_game_state();
+            let mut scores = engine.explain(position);
+            scores.sort_by_key(|score| match score.weighted {
+                Score::Centipawns(centipawns) => Reverse(centipawns.abs()),
+                Score::Mate(_) | Score::TablebaseWin(_) | Score::TablebaseLoss(_) | Score::Draw => {
+                    Reverse(i32::MAX)
+                }
+            });
+            let biggest = scores
+                .iter()
+                .take(CHAT_EXPLAIN_STRATEGIES)
+                .map(|score| format!("{} {}", score.name, score.weighted))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("For {}: {biggest}", position.turn())
+        }
+        _ => return,
+    };
+    if let Err(e) = client
+        .bot_chat_write(&game_id.game_id, chat_line.room, &answer)
+        .await
+    {
+        error!("[{}] Could not answer in chat: {e}", game_id.id);
+    }
 }
 
 async fn abort_game_cleanly_after_error(
@@ -1,14 +1,30 @@
-use std::str::FromStr;
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicI32, Ordering},
+};
 
 use anyhow::Result;
-use shakmaty::{Board, ByRole, Color, uci::UciMove};
+use shakmaty::{
+    Bitboard, Board, ByRole, Chess, Color, File, Move, Position, Rank, Role, Square, san::SanPlus,
+    uci::UciMove,
+};
 
+// default piece values, the engine can load tuned ones at startup
 pub const QUEEN_VALUE: i32 = 900;
 pub const ROOK_VALUE: i32 = 500;
 pub const BISHOP_VALUE: i32 = 300;
 pub const KNIGHT_VALUE: i32 = 300;
 pub const PAWN_VALUE: i32 = 100;
 
+/// pawn, knight, bishop, rook, queen. Global, every game runs with the same configuration
+static PIECE_VALUES: [AtomicI32; 5] = [
+    AtomicI32::new(PAWN_VALUE),
+    AtomicI32::new(KNIGHT_VALUE),
+    AtomicI32::new(BISHOP_VALUE),
+    AtomicI32::new(ROOK_VALUE),
+    AtomicI32::new(QUEEN_VALUE),
+];
+
 pub fn parse_uci_move(move_str: &str) -> Result<UciMove> {
     let uci_move = UciMove::from_str(move_str.trim())?;
 
@@ -24,13 +40,48 @@ pub fn parse_uci_moves(move_str: &str) -> Result<Vec<UciMove>> {
     Ok(uci_moves)
 }
 
+/// plays a move given in SAN (as in PGN files) or UCI, if it's legal in the position
+pub fn play_move(position: &mut Chess, move_str: &str) -> Result<Move> {
+    let legal_move = match SanPlus::from_str(move_str) {
+        Ok(san) => san.san.to_move(position)?,
+        Err(_) => parse_uci_move(move_str)?.to_move(position)?,
+    };
+    position.play_unchecked(legal_move);
+    Ok(legal_move)
+}
+
+/// formats a line of moves played from the given position in SAN, e.g. "Nf3 d5 g3"
+pub fn format_line(position: &Chess, moves: &[Move]) -> String {
+    let mut position = position.clone();
+    moves
+        .iter()
+        .map(|m| SanPlus::from_move_and_play_unchecked(&mut position, *m).to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn piece_value(role: Role) -> i32 {
+    match role {
+        Role::King => 0,
+        role => PIECE_VALUES[role as usize - 1].load(Ordering::Relaxed),
+    }
+}
+
+/// replaces the piece values (pawn to queen). Only meant for startup, a search running at the
+/// same time would see a mix of old and new values
+pub fn set_piece_values(values: [i32; 5]) {
+    for (value, new_value) in PIECE_VALUES.iter().zip(values) {
+        value.store(new_value, Ordering::Relaxed);
+    }
+}
+
 pub fn material_for_side(mat_side: ByRole<u8>) -> i32 {
     let w = mat_side;
-    (w.pawn as i32) * PAWN_VALUE
-        + (w.knight as i32) * KNIGHT_VALUE
-        + (w.bishop as i32) * BISHOP_VALUE
-        + (w.rook as i32) * ROOK_VALUE
-        + (w.queen as i32) * QUEEN_VALUE
+    (w.pawn as i32) * piece_value(Role::Pawn)
+        + (w.knight as i32) * piece_value(Role::Knight)
+        + (w.bishop as i32) * piece_value(Role::Bishop)
+        + (w.rook as i32) * piece_value(Role::Rook)
+        + (w.queen as i32) * piece_value(Role::Queen)
 }
 
 pub fn material_difference(position: &Board) -> i32 {
@@ -38,3 +89,42 @@ pub fn material_difference(position: &Board) -> i32 {
     let black = position.material_side(Color::Black);
     material_for_side(white) - material_for_side(black)
 }
+
+/// 0 for the own back rank up to 7 for the promotion rank
+pub fn relative_rank(square: Square, color: Color) -> usize {
+    match color {
+        Color::White => square.rank() as usize,
+        Color::Black => 7 - square.rank() as usize,
+    }
+}
+
+pub fn adjacent_files(file: File) -> Bitboard {
+    [file.offset(-1), file.offset(1)]
+        .into_iter()
+        .flatten()
+        .fold(Bitboard::EMPTY, |files, file| {
+            files | Bitboard::from_file(file)
+        })
+}
+
+/// all squares on ranks closer to the promotion rank than the given square
+pub fn ranks_in_front(square: Square, color: Color) -> Bitboard {
+    Rank::ALL
+        .into_iter()
+        .filter(|rank| match color {
+            Color::White => *rank > square.rank(),
+            Color::Black => *rank < square.rank(),
+        })
+        .fold(Bitboard::EMPTY, |ranks, rank| {
+            ranks | Bitboard::from_rank(rank)
+        })
+}
+
+/// the position of a FEN that tests know to be valid
+#[cfg(test)]
+pub fn position(fen: &str) -> Chess {
+    fen.parse::<shakmaty::fen::Fen>()
+        .unwrap()
+        .into_position(shakmaty::CastlingMode::Standard)
+        .unwrap()
+}